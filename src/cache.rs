@@ -0,0 +1,244 @@
+use std::{
+    env::{current_dir, temp_dir},
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+
+use rand::{thread_rng, Rng};
+use thiserror::Error;
+
+/// The prefix of the names of the per-run working directories.
+const RUN_DIR_PREFIX: &str = "run_";
+
+/// The directory containing the working directories of all runs, `time-for` in the temp
+/// directory or in the current directory if `relative`.
+///
+/// # Errors
+///
+/// This function will return an error if the current directory can not be accessed.
+pub fn base_dir(relative: bool) -> Result<PathBuf, CacheError> {
+    let parent = match relative {
+        true => current_dir().map_err(|source| CacheError::NoRelativePath { source })?,
+        false => temp_dir(),
+    };
+    Ok(parent.join("time-for"))
+}
+
+/// Creates a new working directory for a single run in the `base` directory.
+///
+/// The name contains the time, the process id and a random suffix if a directory with the
+/// name already exists, so concurrent runs never share a directory.
+///
+/// # Errors
+///
+/// This function will return an error if the directory could not be created.
+pub fn create_run_dir(base: &Path) -> Result<PathBuf, CacheError> {
+    fs::create_dir_all(base).map_err(|source| CacheError::CreateRunDir {
+        dir: base.to_path_buf(),
+        source,
+    })?;
+
+    let name = format!(
+        "{RUN_DIR_PREFIX}{}_{}",
+        chrono::Local::now().format("%Y%m%d_%H%M%S%.3f"),
+        process::id()
+    );
+    let mut dir = base.join(&name);
+    loop {
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            // Another run started in the same millisecond
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                dir = base.join(format!("{name}_{:08x}", thread_rng().gen::<u32>()));
+            }
+            Err(source) => return Err(CacheError::CreateRunDir { dir, source }),
+        }
+    }
+}
+
+/// Removes all files in the run directory `dir` except for the `keep` file, which is the
+/// final file of the run.
+///
+/// The directory itself is removed as well if the `keep` file is outside of it.
+///
+/// # Errors
+///
+/// This function will return an error if the directory could not be read or a file could not
+/// be removed.
+pub fn remove_intermediates(dir: &Path, keep: &Path) -> Result<(), CacheError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path != keep && path.is_file() {
+            fs::remove_file(&path)?;
+        }
+    }
+    if !keep.starts_with(dir) {
+        fs::remove_dir(dir)?;
+    }
+    Ok(())
+}
+
+/// What was removed by [`clean()`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CleanSummary {
+    /// The number of removed run directories
+    pub runs: usize,
+    /// The size of all removed files in bytes
+    pub bytes: u64,
+}
+
+/// Removes the run directories in the `base` directory which were last modified longer than
+/// `older_than` ago.
+///
+/// Only directories created by [`create_run_dir()`] are removed, so other files in the `base`
+/// directory are left alone.
+///
+/// # Errors
+///
+/// This function will return an error if the `base` directory could not be read or a run
+/// directory could not be removed.
+pub fn clean(base: &Path, older_than: Duration) -> Result<CleanSummary, CacheError> {
+    let mut summary = CleanSummary::default();
+    if !base.exists() {
+        return Ok(summary);
+    }
+
+    let now = SystemTime::now();
+    for entry in fs::read_dir(base)? {
+        let entry = entry?;
+        let is_run_dir = entry.file_type()?.is_dir()
+            && entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(RUN_DIR_PREFIX);
+        if !is_run_dir {
+            continue;
+        }
+
+        let age = now
+            .duration_since(entry.metadata()?.modified()?)
+            .unwrap_or_default();
+        if age < older_than {
+            continue;
+        }
+
+        summary.bytes += dir_size(&entry.path())?;
+        fs::remove_dir_all(entry.path())?;
+        summary.runs += 1;
+    }
+    Ok(summary)
+}
+
+/// The size of all files in the `dir` and its subdirectories in bytes.
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += match metadata.is_dir() {
+            true => dir_size(&entry.path())?,
+            false => metadata.len(),
+        };
+    }
+    Ok(size)
+}
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("no relative directory found or non sufficient permissions")]
+    NoRelativePath { source: io::Error },
+    #[error("could not create working directory \"{}\"", dir.display())]
+    CreateRunDir { dir: PathBuf, source: io::Error },
+    #[error("could not access the working directories")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// A new empty directory in the temp directory, unique to the test `name`.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("time-for-cache-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn creates_a_new_run_dir_every_time() {
+        let base = test_dir("create").join("time-for");
+        let dirs: Vec<_> = (0..20).map(|_| create_run_dir(&base).unwrap()).collect();
+        for (i, dir) in dirs.iter().enumerate() {
+            assert!(dir.is_dir());
+            assert!(dir.starts_with(&base));
+            assert!(!dirs[i + 1..].contains(dir), "{}", dir.display());
+        }
+        fs::remove_dir_all(base.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn removes_the_intermediates_but_the_final_file() {
+        let dir = test_dir("intermediates");
+        let keep = dir.join("full.gif");
+        fs::write(dir.join("segment_1.webm"), "clip").unwrap();
+        fs::write(&keep, "gif").unwrap();
+
+        remove_intermediates(&dir, &keep).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        assert!(keep.is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn removes_the_run_dir_if_the_final_file_is_outside() {
+        let base = test_dir("output");
+        let dir = base.join("run_1");
+        let keep = base.join("out.gif");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("full.gif"), "gif").unwrap();
+        fs::write(&keep, "gif").unwrap();
+
+        remove_intermediates(&dir, &keep).unwrap();
+        assert!(!dir.exists());
+        assert!(keep.is_file());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn cleans_only_old_run_dirs() {
+        let base = test_dir("clean");
+        let run = base.join("run_1");
+        fs::create_dir(&run).unwrap();
+        fs::write(run.join("full.gif"), "12345").unwrap();
+        fs::create_dir(base.join("other")).unwrap();
+        fs::write(base.join("run_file"), "").unwrap();
+
+        let summary = clean(&base, Duration::from_secs(60 * 60)).unwrap();
+        assert_eq!(summary, CleanSummary::default());
+        assert!(run.is_dir());
+
+        let summary = clean(&base, Duration::ZERO).unwrap();
+        assert_eq!(summary, CleanSummary { runs: 1, bytes: 5 });
+        assert!(!run.exists());
+        assert!(base.join("other").is_dir());
+        assert!(base.join("run_file").is_file());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn cleaning_a_missing_base_dir_removes_nothing() {
+        let base = env::temp_dir().join(format!("time-for-cache-{}-missing", process::id()));
+        assert_eq!(
+            clean(&base, Duration::ZERO).unwrap(),
+            CleanSummary::default()
+        );
+    }
+}
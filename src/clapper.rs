@@ -1,29 +1,54 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::{
+    clipboard::{CopyFormat, PasteMode},
+    ffmpeg::{Dither, Fit, GifQuality, OutputFormat, StatsMode, TransitionKind},
+    upload::Uploader,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// The query to search a GIF with and add it as a text to that GIF.
     ///
     /// The query string is used to search for a GIF with the TenorAPI.
     /// A random GIF is picked from the first `--considered-gifs` amount of
     /// GIFs. The query is also added as a text to the GIF in the form of:
     /// "time for <QUERY>" if not otherwise specified with `--custom-text`
+    ///
+    /// A query which is also the name of a command, like `delete` or `search`, runs that
+    /// command. Put it after `--` to search for it instead: `time-for -- delete`.
     pub query: Option<String>,
 
     /// A custom text to be placed on the query GIF instead of "time for <QUERY>".
     #[arg(short = 't', long = "text")]
     pub custom_text: Option<String>,
 
+    /// Compose the GIF from the segments of a TOML or YAML storyboard file instead of the
+    /// "look at time" clip and the query.
+    ///
+    /// Every segment has a `query` or a `file` and optionally a `caption` (with `{query}` and
+    /// `{time}` replaced), `considered_gifs`, `start`, `duration` and `min_duration` in
+    /// seconds, `speed`, `reverse`, `boomerang`, `transition` and `transition_duration`.
+    /// `transition` and `transition_duration` can also be set for all segments at the top of
+    /// the file.
+    #[arg(long, conflicts_with_all = ["query", "custom_text"])]
+    pub storyboard: Option<PathBuf>,
+
     /// The number of gifs in the pool to be considered for the GIF based on the query.
     #[arg(short, long, default_value_t = 5)]
     pub considered_gifs: u8,
 
-    // TODO: Implement --no-upload
-    /// Do not upload the GIF to imgur, instead add it directly to the clipboard.
+    /// Do not upload the GIF, instead add the file directly to the clipboard.
     ///
-    /// This will take longer than uploading to imgur, since the file will need to be
-    /// converted to the actual GIF format locally instead of having it be done by imgur.
+    /// GIFs (`--format gif`) are copied as an image, all other formats as a file, which chat
+    /// apps attach when pasting. See `--paste` for how the file is pasted.
     ///
     /// Pasting the GIF directly can also be a size problem, since e.g. Discord only
     /// accepts 8 MB for non-nitro users which GIFs can easily exceed. Imgur accepts up to
@@ -31,6 +56,62 @@ pub struct Args {
     #[arg(short, long, default_value_t = false)]
     pub no_upload: bool,
 
+    /// The services to upload the GIF to, in the order they are tried.
+    ///
+    /// If an upload fails after all retries, the next service in the list is tried.
+    #[arg(
+        short,
+        long = "uploader",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "imgur"
+    )]
+    pub uploaders: Vec<Uploader>,
+
+    /// Upload into the Imgur album with this title, it is created if it doesn't exist yet.
+    ///
+    /// Requires an Imgur account, log in with `time-for login`.
+    #[arg(long)]
+    pub album: Option<String>,
+
+    /// The timeout for a single upload request in seconds.
+    #[arg(long, default_value_t = 120, value_parser = clap::value_parser!(u64).range(1..))]
+    pub upload_timeout: u64,
+
+    /// How often an upload is retried after a rate limit (429) or server error (5xx),
+    /// waiting twice as long before every retry.
+    #[arg(long, default_value_t = 3)]
+    pub upload_retries: u32,
+
+    /// The maximum size of the GIF in MB (1024 * 1024 bytes).
+    ///
+    /// If the GIF is larger, it is re-encoded with a lower bitrate, frame rate and resolution
+    /// until it fits. Defaults to the limit of the uploaders, or 8 MB when the file is sent to a
+    /// Discord webhook directly.
+    #[arg(long, value_parser = parse_max_size)]
+    pub max_size: Option<f64>,
+
+    /// What is done with the link after uploading, or the file with `--no-upload`.
+    ///
+    /// `type` pastes it into the focused window, which needs wtype on Wayland. When posting to
+    /// a webhook or writing the file to stdout with `--output -` the link is only copied to the
+    /// clipboard instead. On Wayland the clipboard needs wl-copy (wl-clipboard), on X11 xclip
+    /// is used if it is installed.
+    #[arg(long, value_enum, default_value_t = PasteMode::Type)]
+    pub paste: PasteMode,
+
+    /// How the link is copied and pasted, with the text on the GIF as the alt text.
+    ///
+    /// With `--no-upload`, `link` copies the file itself, `markdown`/`html`/`bbcode` use its
+    /// `file://` URI and `path` its absolute path.
+    #[arg(long, value_enum, default_value_t = CopyFormat::Link)]
+    pub copy_as: CopyFormat,
+
+    /// Also copy the link to the primary selection, which is pasted with a middle click on
+    /// Linux.
+    #[arg(long, default_value_t = false)]
+    pub primary: bool,
+
     /// Open the created GIF/webm file in the Windows explorer after creating it.
     #[arg(short = 'x', long, default_value_t = false)]
     pub explorer: bool,
@@ -40,6 +121,37 @@ pub struct Args {
     #[arg(short, long, default_value_t = false)]
     pub relative: bool,
 
+    /// Save the final file to this path instead of only in the working directory.
+    ///
+    /// If the path is a directory (or ends with a `/`), the file is saved in it with the name
+    /// of the `--output-name` template. With `-` the file is written to stdout, for piping it
+    /// into other programs, and all other messages are written to stderr.
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// The file name template used when the `--output` is a directory.
+    ///
+    /// `{date}` (2024-01-31), `{time}` (13-37-00), `{query}` (with special characters replaced
+    /// by `_`, "time" without a query) and `{ext}` (the extension of the `--format`) are
+    /// replaced with their values.
+    #[arg(long, default_value = "{date}_{time}_{query}.{ext}")]
+    pub output_name: String,
+
+    /// Keep the downloaded clips and other intermediate files of the run.
+    ///
+    /// Every run uses its own directory in the `time-for` directory, in which only the final
+    /// file is kept by default. Remove old runs with `time-for cache clean`.
+    #[arg(long, default_value_t = false)]
+    pub keep_intermediates: bool,
+
+    /// Print a JSON report of the run to stdout, the status messages go to stderr.
+    ///
+    /// The report contains the link, the local path, the chosen GIFs with their captions and
+    /// durations, how long every stage took and all warnings. If the run fails, an object with
+    /// the `error` is printed instead. Can't be used with `--output -`.
+    #[arg(long, default_value_t = false)]
+    pub json: bool,
+
     /// Open the created gif/webm file in the default application.
     #[arg(short, long, default_value_t = false)]
     pub open: bool,
@@ -47,4 +159,287 @@ pub struct Args {
     /// Add a delay/offset to the time on the gif to compensate for the generation time.
     #[arg(short, long, default_value_t = 0)]
     pub delay: i32,
+
+    /// Start the query clip at this time in seconds.
+    ///
+    /// This and the other clip options are applied to the query clip, or to the "look at
+    /// time" clip if there is no query. Set them per segment in a `--storyboard` instead.
+    #[arg(long, conflicts_with = "storyboard")]
+    pub start: Option<f64>,
+
+    /// Cut the query clip after this many seconds.
+    #[arg(long, conflicts_with = "storyboard")]
+    pub max_duration: Option<f64>,
+
+    /// Speed up the query clip by this factor, e.g. `0.5` plays it at half speed.
+    #[arg(long, value_parser = parse_speed, conflicts_with = "storyboard")]
+    pub speed: Option<f64>,
+
+    /// Play the query clip backwards.
+    #[arg(long, default_value_t = false, conflicts_with = "storyboard")]
+    pub reverse: bool,
+
+    /// Play the query clip forwards and then backwards.
+    #[arg(long, default_value_t = false, conflicts_with = "storyboard")]
+    pub boomerang: bool,
+
+    /// Repeat the query clip until it is at least this many seconds long.
+    #[arg(long, conflicts_with = "storyboard")]
+    pub min_duration: Option<f64>,
+
+    /// The file format of the created file.
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Webm)]
+    pub format: OutputFormat,
+
+    /// The size vs. quality preset for GIFs (`--format gif`).
+    ///
+    /// `small` lowers the frame rate and colors and compresses lossily with gifsicle (if it is
+    /// installed), `balanced` keeps all colors at 15 fps and `best` also keeps the frame rate.
+    /// The other GIF options override single settings of the preset.
+    #[arg(long, value_enum, default_value_t = GifQuality::Balanced)]
+    pub quality: GifQuality,
+
+    /// The frame rate of GIFs, `0` keeps the frame rate of the clips.
+    #[arg(long)]
+    pub gif_fps: Option<u32>,
+
+    /// The maximum number of colors in the palette of GIFs (2-256).
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..=256))]
+    pub colors: Option<u32>,
+
+    /// The dithering algorithm used for colors missing in the palette of GIFs.
+    #[arg(long, value_enum)]
+    pub dither: Option<Dither>,
+
+    /// Which pixels are counted to pick the colors of the palette of GIFs.
+    #[arg(long, value_enum)]
+    pub stats_mode: Option<StatsMode>,
+
+    /// Generate a palette for every frame of GIFs instead of one for the whole clip.
+    #[arg(long, default_value_t = false)]
+    pub per_frame_palette: bool,
+
+    /// Compress GIFs lossily with gifsicle at this level (usually 30-200), `0` disables it.
+    #[arg(long)]
+    pub lossy: Option<u32>,
+
+    /// The resolution all clips are scaled to, in the form of `<WIDTH>x<HEIGHT>`.
+    #[arg(short, long, default_value = "480x270", value_parser = parse_size)]
+    pub size: (u32, u32),
+
+    /// How clips with a different aspect ratio are fitted into the `--size`.
+    #[arg(long, value_enum, default_value_t = Fit::Letterbox)]
+    pub fit: Fit,
+
+    /// The transition from the time clip to the query clip.
+    #[arg(long, value_enum, default_value_t = TransitionKind::Cut)]
+    pub transition: TransitionKind,
+
+    /// The duration of the `--transition` in seconds.
+    ///
+    /// The clips overlap during the transition, so it is at most half as long as the shorter
+    /// clip.
+    #[arg(long, default_value_t = 0.5, value_parser = parse_transition_duration)]
+    pub transition_duration: f64,
+
+    /// Post the GIF to an incoming webhook. Can be given multiple times.
+    ///
+    /// Discord (discord.com) and Slack (hooks.slack.com) webhooks are recognized by their URL,
+    /// any other URL is treated as a Mattermost webhook. The uploaded link is posted together
+    /// with the `--webhook-message`. Discord webhooks also accept the file itself when it
+    /// wasn't uploaded (`--no-upload`).
+    ///
+    /// When posting to a webhook the link is not pasted into the focused window, see `--paste`.
+    #[arg(short, long = "webhook")]
+    pub webhooks: Vec<String>,
+
+    /// The message posted to the webhooks.
+    ///
+    /// `{query}`, `{text}` (the text on the GIF) and `{link}` are replaced with their values.
+    /// Defaults to "time for {query}!" or just "{text}" if there is no query.
+    #[arg(long)]
+    pub webhook_message: Option<String>,
+}
+
+/// Parses a resolution in the form of `480x270`.
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let (width, height) = size
+        .split_once(['x', 'X'])
+        .ok_or("the size must be in the form of <WIDTH>x<HEIGHT>")?;
+    let width: u32 = width
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid width: {e}"))?;
+    let height: u32 = height
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid height: {e}"))?;
+
+    // yuv420p needs an even resolution
+    if width == 0 || height == 0 || width % 2 != 0 || height % 2 != 0 {
+        return Err("the width and height must be even and larger than 0".to_string());
+    }
+    Ok((width, height))
+}
+
+/// Parses a size limit in MB, which has to be larger than 0.
+fn parse_max_size(max_size: &str) -> Result<f64, String> {
+    let max_size: f64 = max_size.parse().map_err(|e| format!("invalid size: {e}"))?;
+    if !(max_size > 0.0 && max_size.is_finite()) {
+        return Err("the maximum size must be larger than 0".to_string());
+    }
+    Ok(max_size)
+}
+
+/// Parses a speed factor, which has to be larger than 0.
+fn parse_speed(speed: &str) -> Result<f64, String> {
+    let speed: f64 = speed.parse().map_err(|e| format!("invalid speed: {e}"))?;
+    if !(speed > 0.0 && speed.is_finite()) {
+        return Err("the speed must be larger than 0".to_string());
+    }
+    Ok(speed)
+}
+
+/// Parses a transition duration in seconds, which has to be larger than 0.
+fn parse_transition_duration(duration: &str) -> Result<f64, String> {
+    let duration: f64 = duration
+        .parse()
+        .map_err(|e| format!("invalid duration: {e}"))?;
+    if !(duration > 0.0 && duration.is_finite()) {
+        return Err("the transition duration must be larger than 0".to_string());
+    }
+    Ok(duration)
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Delete an uploaded GIF again.
+    ///
+    /// Only uploads made by time-for can be deleted, since the token needed for deleting
+    /// is stored when uploading. Catbox uploads can not be deleted.
+    Delete {
+        /// The link or id of the upload to delete.
+        #[arg(required_unless_present = "last")]
+        target: Option<String>,
+
+        /// Delete the most recent upload.
+        #[arg(short, long, conflicts_with = "target")]
+        last: bool,
+    },
+    /// Search Tenor and list the GIFs that could be picked for the query with their
+    /// resolution, frame rate, duration and codec.
+    Search {
+        /// The query to search GIFs with.
+        query: String,
+
+        /// The number of GIFs to list.
+        #[arg(short, long, default_value_t = 5)]
+        considered_gifs: u8,
+    },
+    /// Show the resolution, frame rate, duration, codec and audio of a video file or url.
+    Preview {
+        /// The path or url of the video.
+        input: String,
+    },
+    /// Log in to Imgur, so GIFs are uploaded to your account instead of anonymously.
+    ///
+    /// Opens the Imgur login in the browser. After logging in, paste the address of the page
+    /// you are redirected to.
+    Login,
+    /// Log out of Imgur, so GIFs are uploaded anonymously again.
+    Logout,
+    /// Manage the working directories of previous runs.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// Remove the working directories of previous runs, including their final files.
+    Clean {
+        /// Only remove runs which were last modified more than this many hours ago.
+        #[arg(long, default_value_t = 1)]
+        older_than: u64,
+
+        /// Clean the relative directory (./time-for) instead of the temp directory.
+        #[arg(short, long, default_value_t = false)]
+        relative: bool,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subcommand_names_run_the_subcommand() {
+        let args = Args::try_parse_from(["time-for", "delete", "--last"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Delete { last: true, .. })
+        ));
+        assert_eq!(args.query, None);
+    }
+
+    #[test]
+    fn subcommand_names_after_double_dash_are_queries() {
+        for name in ["delete", "search", "preview", "login", "logout", "cache"] {
+            let args = Args::try_parse_from(["time-for", "--", name]).unwrap();
+            assert!(args.command.is_none(), "{name}");
+            assert_eq!(args.query.as_deref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn queries_are_not_subcommands() {
+        let args = Args::try_parse_from(["time-for", "coffee"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.query.as_deref(), Some("coffee"));
+    }
+
+    #[test]
+    fn max_size_must_be_positive() {
+        let args = Args::try_parse_from(["time-for", "--max-size", "2.5", "coffee"]).unwrap();
+        assert_eq!(args.max_size, Some(2.5));
+        for max_size in ["0", "-1", "NaN", "inf", "big"] {
+            assert!(
+                Args::try_parse_from(["time-for", "--max-size", max_size, "coffee"]).is_err(),
+                "{max_size}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_even_sizes() {
+        assert_eq!(parse_size("480x270"), Ok((480, 270)));
+        assert_eq!(parse_size("640X360"), Ok((640, 360)));
+        assert_eq!(parse_size(" 320 x 180 "), Ok((320, 180)));
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for size in [
+            "480", "0x270", "480x0", "481x270", "480x271", "-480x270", "ax270", "480x", "x270",
+        ] {
+            assert!(parse_size(size).is_err(), "{size}");
+        }
+    }
+
+    #[test]
+    fn speed_must_be_positive() {
+        assert_eq!(parse_speed("1.5"), Ok(1.5));
+        for speed in ["0", "-2", "NaN", "inf", "fast"] {
+            assert!(parse_speed(speed).is_err(), "{speed}");
+        }
+    }
+
+    #[test]
+    fn transition_duration_must_be_positive() {
+        assert_eq!(parse_transition_duration("0.25"), Ok(0.25));
+        for duration in ["0", "-1", "NaN", "inf", "long"] {
+            assert!(parse_transition_duration(duration).is_err(), "{duration}");
+        }
+    }
 }
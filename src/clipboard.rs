@@ -0,0 +1,440 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use arboard::Clipboard;
+use clap::ValueEnum;
+use tfc::{Context, Key, KeyboardContext};
+use thiserror::Error;
+
+/// How long the clipboard is kept after pasting, so the focused window can request it.
+const PASTE_DELAY: Duration = Duration::from_millis(200);
+
+/// What is done with the link after uploading, or with the file if it isn't uploaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum PasteMode {
+    /// Only print the link
+    None,
+    /// Copy the link or file to the clipboard
+    Clipboard,
+    /// Copy the link or file to the clipboard and paste it into the focused window with Ctrl+V
+    #[default]
+    Type,
+}
+
+/// The text the link is copied as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum CopyFormat {
+    /// The bare link
+    #[default]
+    Link,
+    /// A Markdown image: `![alt](link)`
+    Markdown,
+    /// An HTML image: `<img src="link" alt="alt">`
+    Html,
+    /// A BBCode image: `[img alt="alt"]link[/img]`
+    Bbcode,
+    /// The absolute path of the local file instead of the link
+    Path,
+}
+
+impl CopyFormat {
+    /// Formats the `link` of the file at `path` with the `alt` text describing it.
+    pub fn format(&self, link: &str, path: &Path, alt: &str) -> String {
+        match self {
+            CopyFormat::Link => link.to_string(),
+            CopyFormat::Markdown => {
+                let alt = alt.replace('[', "\\[").replace(']', "\\]");
+                // Brackets and parentheses would end the link or the image early
+                let link = link
+                    .replace(' ', "%20")
+                    .replace('(', "%28")
+                    .replace(')', "%29")
+                    .replace('[', "%5B")
+                    .replace(']', "%5D");
+                format!("![{alt}]({link})")
+            }
+            CopyFormat::Html => {
+                format!(
+                    r#"<img src="{}" alt="{}">"#,
+                    escape_html(link),
+                    escape_html(alt)
+                )
+            }
+            CopyFormat::Bbcode => format!(r#"[img alt="{}"]{link}[/img]"#, alt.replace('"', "'")),
+            CopyFormat::Path => path.to_string_lossy().to_string(),
+        }
+    }
+}
+
+/// Escapes the characters with a special meaning in HTML attributes.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The display server the clipboard belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Display {
+    Wayland,
+    X11,
+    /// Windows and macOS, which always have a clipboard
+    Native,
+}
+
+impl Display {
+    /// Detects the display server of the session from the environment.
+    fn detect() -> Result<Display, ClipboardError> {
+        if !cfg!(target_os = "linux") {
+            return Ok(Display::Native);
+        }
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            Ok(Display::Wayland)
+        } else if env::var_os("DISPLAY").is_some() {
+            Ok(Display::X11)
+        } else {
+            Err(ClipboardError::NoDisplay)
+        }
+    }
+}
+
+/// What is put on the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Content<'a> {
+    Text(&'a str),
+    /// A media file, GIFs are copied as the image itself (`image/gif`), all other files as a
+    /// file list (`text/uri-list`), which chat apps accept as an attached file
+    File(&'a Path),
+}
+
+impl Content<'_> {
+    /// The mime type and data offered on the clipboard by wl-copy and xclip.
+    fn payload(&self) -> Result<(&'static str, Vec<u8>), ClipboardError> {
+        match self {
+            Content::Text(text) => Ok(("text/plain;charset=utf-8", text.as_bytes().to_vec())),
+            Content::File(file) if file.extension().is_some_and(|ext| ext == "gif") => {
+                Ok(("image/gif", fs::read(file)?))
+            }
+            Content::File(file) => Ok((
+                "text/uri-list",
+                format!("{}\r\n", file_uri(&std::path::absolute(file)?)).into_bytes(),
+            )),
+        }
+    }
+}
+
+/// Copies the `content` to the clipboard and, if `primary`, also to the primary selection
+/// (pasted with a middle click on Linux).
+///
+/// On Linux the clipboard is only served as long as the program owning it runs, so wl-copy
+/// (Wayland) or xclip (X11) are used if they are installed, which keep serving it in the
+/// background. Otherwise the clipboard is handed to a clipboard manager when this program
+/// exits, if there is one.
+///
+/// # Errors
+///
+/// This function will return an error if there is no display on Linux or the clipboard could
+/// not be set.
+pub fn copy(content: &Content, primary: bool) -> Result<(), ClipboardError> {
+    set(content, primary)?;
+    Ok(())
+}
+
+/// Copies the `content` to the clipboard and pastes it into the focused window by emulating
+/// Ctrl+V.
+///
+/// On Wayland the key presses are emulated with wtype, since other programs can't send key
+/// presses to windows there.
+///
+/// # Errors
+///
+/// This function will return an error if the content could not be copied or the key presses
+/// could not be emulated.
+pub fn paste(content: &Content, primary: bool) -> Result<(), ClipboardError> {
+    // Keep serving the clipboard until the focused window had time to request it
+    let _clipboard = set(content, primary)?;
+
+    if Display::detect()? == Display::Wayland {
+        run("wtype", &["-M", "ctrl", "-k", "v", "-m", "ctrl"], None).map_err(|e| match e {
+            ClipboardError::CommandNotFound { .. } => ClipboardError::WtypeNotFound,
+            e => e,
+        })?;
+    } else {
+        let mut ctx = Context::new()?;
+        ctx.key_down(Key::Control)?;
+        ctx.key_click(Key::V)?;
+        ctx.key_up(Key::Control)?;
+    }
+    thread::sleep(PASTE_DELAY);
+    Ok(())
+}
+
+/// Sets the clipboard with the first way that works on the display, returning the clipboard
+/// if it is served by this program.
+fn set(content: &Content, primary: bool) -> Result<Option<Clipboard>, ClipboardError> {
+    match Display::detect()? {
+        Display::Wayland => match wl_copy(content, primary) {
+            // XWayland might still be available
+            Err(ClipboardError::WlCopyNotFound) if env::var_os("DISPLAY").is_some() => {}
+            result => return result.map(|_| None),
+        },
+        Display::X11 => match xclip(content, primary) {
+            Err(ClipboardError::CommandNotFound { .. }) => {}
+            result => return result.map(|_| None),
+        },
+        Display::Native => {}
+    }
+
+    let mut clipboard = Clipboard::new()?;
+    match content {
+        Content::Text(text) => {
+            clipboard.set_text(*text)?;
+            #[cfg(target_os = "linux")]
+            if primary {
+                use arboard::{LinuxClipboardKind, SetExtLinux};
+                clipboard
+                    .set()
+                    .clipboard(LinuxClipboardKind::Primary)
+                    .text(*text)?;
+            }
+        }
+        Content::File(file) => clipboard.set().file_list(&[file])?,
+    }
+    Ok(Some(clipboard))
+}
+
+/// Copies the `content` with wl-copy, which keeps serving it after this program exits.
+fn wl_copy(content: &Content, primary: bool) -> Result<(), ClipboardError> {
+    let (mime, data) = content.payload()?;
+    run("wl-copy", &["--type", mime], Some(&data)).map_err(|e| match e {
+        ClipboardError::CommandNotFound { .. } => ClipboardError::WlCopyNotFound,
+        e => e,
+    })?;
+    if primary {
+        run("wl-copy", &["--primary", "--type", mime], Some(&data))?;
+    }
+    Ok(())
+}
+
+/// Copies the `content` with xclip, which keeps serving it after this program exits.
+fn xclip(content: &Content, primary: bool) -> Result<(), ClipboardError> {
+    let (mime, data) = content.payload()?;
+    // xclip only offers UTF8_STRING for text if it isn't given a target
+    let target: &[&str] = match content {
+        Content::Text(_) => &[],
+        Content::File(_) => &["-t", mime],
+    };
+    run(
+        "xclip",
+        &[&["-selection", "clipboard"], target].concat(),
+        Some(&data),
+    )?;
+    if primary {
+        run(
+            "xclip",
+            &[&["-selection", "primary"], target].concat(),
+            Some(&data),
+        )?;
+    }
+    Ok(())
+}
+
+/// The `file://` URI of the absolute `path`, with all special characters percent-encoded.
+///
+/// Windows paths get forward slashes, e.g. `C:\Users\me` becomes `file:///C:/Users/me` and
+/// the share `\\server\gifs` becomes `file://server/gifs`.
+pub fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    #[cfg(windows)]
+    let path = match path.replace('\\', "/") {
+        path if path.starts_with("//") => path[2..].to_string(),
+        path if path.starts_with('/') => path,
+        path => format!("/{path}"),
+    };
+
+    let mut uri = "file://".to_string();
+    for (i, byte) in path.bytes().enumerate() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            // The colon after the drive letter, e.g. in `/C:/Users`
+            b':' if cfg!(windows) && i == 2 => uri.push(':'),
+            _ => uri += &format!("%{byte:02X}"),
+        }
+    }
+    uri
+}
+
+/// Runs the `program` with the `args`, writing the `input` to its stdin.
+///
+/// The output isn't captured, since wl-copy and xclip keep running in the background with the
+/// same stdout and stderr, which would never be closed.
+fn run(program: &str, args: &[&str], input: Option<&[u8]>) -> Result<(), ClipboardError> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|source| match source.kind() {
+            io::ErrorKind::NotFound => ClipboardError::CommandNotFound {
+                program: program.to_string(),
+            },
+            _ => ClipboardError::Io { source },
+        })?;
+
+    // The stdin is closed when dropped, so the program knows the input is complete
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input)?;
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(ClipboardError::CommandFailed {
+            program: program.to_string(),
+            exit_code: status.code(),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum ClipboardError {
+    #[error("no display found, neither WAYLAND_DISPLAY nor DISPLAY is set (use `--paste none`)")]
+    NoDisplay,
+    #[error(
+        "wl-copy can not be found in path, install wl-clipboard to use the clipboard on Wayland"
+    )]
+    WlCopyNotFound,
+    #[error(
+        "wtype can not be found in path, install it to paste on Wayland or use `--paste clipboard`"
+    )]
+    WtypeNotFound,
+    #[error("{program} can not be found in path")]
+    CommandNotFound { program: String },
+    #[error("{program} failed (exit code {})", exit_code.map_or("None".to_string(), |c| c.to_string()))]
+    CommandFailed {
+        program: String,
+        exit_code: Option<i32>,
+    },
+    #[error("could not access the clipboard")]
+    Clipboard {
+        #[from]
+        source: arboard::Error,
+    },
+    #[error("could not emulate the key presses to paste")]
+    Keyboard {
+        #[from]
+        source: tfc::Error,
+    },
+    #[error("Unknown Io error")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINK: &str = "https://i.imgur.com/abc.gif";
+
+    fn format(copy_as: CopyFormat, link: &str, alt: &str) -> String {
+        copy_as.format(link, Path::new("/tmp/time-for/full.gif"), alt)
+    }
+
+    #[test]
+    fn formats_the_bare_link() {
+        assert_eq!(format(CopyFormat::Link, LINK, "time for tea"), LINK);
+    }
+
+    #[test]
+    fn formats_markdown_images() {
+        assert_eq!(
+            format(CopyFormat::Markdown, LINK, "time for tea"),
+            "![time for tea](https://i.imgur.com/abc.gif)"
+        );
+        assert_eq!(
+            format(CopyFormat::Markdown, LINK, "time for [tea]"),
+            r"![time for \[tea\]](https://i.imgur.com/abc.gif)"
+        );
+    }
+
+    #[test]
+    fn escapes_markdown_links() {
+        assert_eq!(
+            format(CopyFormat::Markdown, "file:///tmp/a (1) [b].gif", "tea"),
+            "![tea](file:///tmp/a%20%281%29%20%5Bb%5D.gif)"
+        );
+    }
+
+    #[test]
+    fn formats_html_images() {
+        assert_eq!(
+            format(CopyFormat::Html, LINK, r#"tea & "cake" <3"#),
+            r#"<img src="https://i.imgur.com/abc.gif" alt="tea &amp; &quot;cake&quot; &lt;3">"#
+        );
+    }
+
+    #[test]
+    fn formats_bbcode_images() {
+        assert_eq!(
+            format(CopyFormat::Bbcode, LINK, r#"time for "tea""#),
+            r#"[img alt="time for 'tea'"]https://i.imgur.com/abc.gif[/img]"#
+        );
+    }
+
+    #[test]
+    fn formats_the_path() {
+        assert_eq!(
+            format(CopyFormat::Path, LINK, "time for tea"),
+            "/tmp/time-for/full.gif"
+        );
+    }
+
+    #[test]
+    fn file_uri_keeps_safe_characters() {
+        assert_eq!(
+            file_uri(Path::new("/tmp/time-for/run_1/full~.gif")),
+            "file:///tmp/time-for/run_1/full~.gif"
+        );
+    }
+
+    #[test]
+    fn file_uri_encodes_special_characters() {
+        assert_eq!(
+            file_uri(Path::new("/home/me/my gifs/it's 100%#1.gif")),
+            "file:///home/me/my%20gifs/it%27s%20100%25%231.gif"
+        );
+        assert_eq!(
+            file_uri(Path::new("/tmp/Jürgen.gif")),
+            "file:///tmp/J%C3%BCrgen.gif"
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn file_uri_of_windows_paths() {
+        assert_eq!(
+            file_uri(Path::new(r"C:\Users\me\my gifs\full.gif")),
+            "file:///C:/Users/me/my%20gifs/full.gif"
+        );
+        assert_eq!(
+            file_uri(Path::new(r"\\server\gifs\full.gif")),
+            "file://server/gifs/full.gif"
+        );
+    }
+}
@@ -1,175 +1,409 @@
+use clap::ValueEnum;
 use std::{
-    env::temp_dir,
-    fs::{self, File},
-    io::{self, Write},
-    path::Path,
-    process::{Child, Command, Stdio},
+    io,
+    path::{Path, PathBuf},
+    process::Command,
 };
 use thiserror::Error;
 
-/// Overlays the `text` on the bottom of the `input_file` and saves it to the `output_file`.
-/// It also scales the file to 480x270 so that all files have the same size and can be
-/// stitched together faster.
+mod command;
+mod effects;
+mod format;
+mod gif;
+mod progress;
+mod transition;
+
+pub use command::FfmpegCommand;
+pub use effects::Effects;
+pub use format::OutputFormat;
+pub use gif::{Dither, GifOptions, GifQuality, StatsMode};
+pub use progress::{ProgressEvent, ProgressParser};
+use transition::join;
+pub use transition::{Transition, TransitionKind};
+
+/// The drawtext filter which draws the `text` centered on the bottom of the video.
 ///
-/// # Panics
+/// The text is escaped for the filter options and the options are quoted for the filter
+/// graph, where a `'` has to close the quote, be escaped and open it again. `%` is drawn as
+/// is, since the text expansion is turned off.
+fn drawtext_filter(text: &str, fontsize: u32) -> String {
+    let text = text
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace(':', "\\:");
+    // TODO: Find a better way to handle fonts
+    let options = format!("fontfile=C\\:/Windows/fonts/impact.ttf:fontcolor=white:borderw=3:fontsize={}:x=(w-text_w)/2:y=(h-text_h)-20:expansion=none:text={}", fontsize, text);
+    format!("drawtext='{}'", options.replace('\'', r"'\''"))
+}
+
+/// How a clip is fitted into the target resolution if the aspect ratios differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Fit {
+    /// Scale the clip to fit inside and fill the rest with black bars
+    #[default]
+    Letterbox,
+    /// Scale the clip to cover the whole frame and cut off what doesn't fit
+    Crop,
+}
+
+/// The common format all clips are normalized to, so they can be joined in one filter graph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normalization {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub fit: Fit,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self {
+            width: 480,
+            height: 270,
+            fps: 25,
+            fit: Fit::Letterbox,
+        }
+    }
+}
+
+impl Normalization {
+    /// The pixel format all clips are encoded with
+    pub const PIXEL_FORMAT: &'static str = "yuv420p";
+
+    /// The filter chain which scales, pads or crops the clip and sets its frame rate and pixel
+    /// format.
+    pub fn filter(&self) -> String {
+        let (w, h) = (self.width, self.height);
+        let fit = match self.fit {
+            Fit::Letterbox => format!(
+                "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2"
+            ),
+            Fit::Crop => {
+                format!("scale={w}:{h}:force_original_aspect_ratio=increase,crop={w}:{h}")
+            }
+        };
+        format!(
+            "{fit},setsar=1,fps={},format={}",
+            self.fps,
+            Self::PIXEL_FORMAT
+        )
+    }
+}
+
+/// One clip of the final file together with the text drawn on it.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub file: PathBuf,
+    /// The text drawn on the clip, nothing is drawn if it is empty
+    pub text: String,
+    /// The part of the file used, `None` uses the whole file
+    pub trim: Option<Trim>,
+    /// The duration of the clip in seconds after trimming, needed to place the transitions
+    pub duration: f64,
+    /// The changes to the playback of the clip
+    pub effects: Effects,
+    /// The transition from the previous segment, ignored for the first one
+    pub transition: Transition,
+}
+
+impl Segment {
+    /// The duration of the clip in seconds after applying the effects.
+    pub fn output_duration(&self) -> f64 {
+        self.effects.duration(self.duration)
+    }
+}
+
+/// The part of a file used for a [`Segment`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Trim {
+    /// The time in seconds the clip starts at
+    pub start: f64,
+    /// The length in seconds, `None` uses the rest of the file
+    pub duration: Option<f64>,
+}
+
+/// The duration in seconds of the file rendered from the `segments`, which is shorter than
+/// all segments together since the clips overlap during their transitions.
+pub fn rendered_duration(segments: &[Segment]) -> f64 {
+    segments.iter().map(Segment::output_duration).sum::<f64>()
+        - segments
+            .windows(2)
+            .map(|pair| {
+                pair[1]
+                    .transition
+                    .overlap(pair[0].output_duration(), pair[1].output_duration())
+            })
+            .sum::<f64>()
+}
+
+/// Normalizes all `segments`, draws their text on them and joins them with their transitions
+/// into the `output_file` with a single ffmpeg invocation, without writing any intermediate
+/// files.
 ///
-/// Panics if the ffmpeg command could not be run.
+/// The file is encoded to the `format`, GIFs are encoded with the `gif` options.
+/// `on_progress` is called for every progress update of ffmpeg.
 ///
 /// # Errors
 ///
-/// This function will return an error if the `Command spawn()` command returns an error.
-pub fn add_text(
-    input_file: &Path,
-    text: &str,
+/// This function will return an error if the ffmpeg command could not be run or failed.
+pub fn render(
+    segments: &[Segment],
+    normalization: &Normalization,
+    format: OutputFormat,
+    gif: &GifOptions,
     output_file: &Path,
-) -> Result<Child> {
-    let text = text.to_string().replace(':', "\\:");
-    // TODO: Find a better way to handle fonts
-    let vf_text = format!("drawtext='fontfile=C\\:/Windows/fonts/impact.ttf:fontcolor=white:borderw=3:fontsize=22:x=(w-text_w)/2:y=(h-text_h)-20:text={}'", text);
-    Command::new("ffmpeg")
-        .arg("-i")
-        .arg(input_file)
-        .args(["-vf", &vf_text])
-        .arg("-y")
-        .arg(output_file)
-        .stderr(Stdio::null())
-        .spawn()
-        .map_err(|e| e.into())
+    on_progress: impl FnMut(&ProgressEvent),
+) -> Result<()> {
+    render_command(segments, normalization, format, gif, output_file).run_with_progress(on_progress)
 }
 
-/// Scales the `input_file` to the given `scale` (x, y) with the default value of `480x270`
-/// using ffmpeg with codec copy `-c copy` meaning that this runs blazing fast™.
+/// Renders the `segments` like [`render()`] without blocking the async runtime.
 ///
 /// # Errors
 ///
-/// This function will return an error if the `Command spawn()` command returns an error.
-pub fn scale(
-    input_file: &Path,
-    scale: Option<(u32, u32)>,
+/// This function will return an error if the ffmpeg command could not be run or failed.
+#[cfg(feature = "async")]
+pub async fn render_async(
+    segments: &[Segment],
+    normalization: &Normalization,
+    format: OutputFormat,
+    gif: &GifOptions,
     output_file: &Path,
-) -> Result<Child> {
-    let scale = scale.unwrap_or((480, 270));
-    Command::new("ffmpeg")
-        .arg("-i")
-        .arg(input_file)
-        .arg("-s")
-        .arg(format!("{}x{}", scale.0, scale.1))
-        .arg("-c")
-        .arg("copy")
-        .arg("-y")
-        .arg(output_file)
-        .stderr(Stdio::null())
-        .spawn()
-        .map_err(|e| e.into())
-}
-
-/// Converts the given file to a gif and and returns the handle to the spawned child process.
-///
-/// The output file will have the same name as the input but with the `.gif` file extension.
-///
-/// The ffmpeg command used looks like this:
-///
-/// ```powershell
-/// ffmpeg -i <input_file> -y -filter_complex "[0:v] split [a][b];[a] palettegen [p];[b][p] paletteuse" <input_file.gif>
-/// ```
+    on_progress: impl FnMut(&ProgressEvent),
+) -> Result<()> {
+    render_command(segments, normalization, format, gif, output_file)
+        .run_with_progress_async(on_progress)
+        .await
+}
+
+/// The command of [`render()`].
+fn render_command(
+    segments: &[Segment],
+    normalization: &Normalization,
+    format: OutputFormat,
+    gif: &GifOptions,
+    output_file: &Path,
+) -> FfmpegCommand {
+    segments
+        .iter()
+        .fold(FfmpegCommand::new(), |command, segment| {
+            // Seeking before the input only decodes the used part of the file
+            let command = match segment.trim {
+                Some(Trim { start, duration }) => {
+                    let command = command.args(["-ss", &start.to_string()]);
+                    match duration {
+                        Some(duration) => command.args(["-t", &duration.to_string()]),
+                        None => command,
+                    }
+                }
+                None => command,
+            };
+            command.input(&segment.file)
+        })
+        .filter_complex(&filter_graph(
+            segments,
+            normalization,
+            format.needs_palette().then_some(gif),
+        ))
+        .map("[out]")
+        .args(format.encoder_args(None))
+        .no_audio()
+        .output(output_file)
+}
+
+/// Builds the filter graph which applies the effects of every segment to its input, normalizes
+/// it, draws the text of the segment on it and joins all of them with their transitions into
+/// the `[out]` stream, optionally with the palette filter of the `gif` options.
 ///
-/// # Errors
+/// For two segments with a cut the graph looks like this (without the palette):
 ///
-/// This function will return an error if there is an error spawning the child.
-pub fn convert_to_gif(input_file: &Path) -> Result<Child> {
-    let handle = Command::new("ffmpeg")
-        .arg("-i")
-        .arg(input_file)
-        .arg("-y")
-        .args([
-            "-filter_complex",
-            "[0:v] split [a][b];[a] palettegen [p];[b][p] paletteuse",
-        ])
-        .arg(&input_file.with_extension("gif"))
-        .stderr(Stdio::null())
-        .spawn()?;
-    Ok(handle)
+/// ```text
+/// [0:v] <effects>,<normalize>,<loop>,drawtext=... [v0];
+/// [1:v] <effects>,<normalize>,<loop>,drawtext=... [v1];
+/// [v0][v1] concat=n=2:v=1:a=0 [out]
+/// ```
+pub fn filter_graph(
+    segments: &[Segment],
+    normalization: &Normalization,
+    gif: Option<&GifOptions>,
+) -> String {
+    // The font size of 22 was chosen for a height of 270
+    let fontsize = (normalization.height * 22 / 270).max(1);
+
+    let mut graph = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        graph += &format!("[{i}:v] ");
+        if let Some(filter) = segment.effects.filter(i) {
+            graph += &format!("{filter},");
+        }
+        graph += &normalization.filter();
+        if let Some(filter) = segment.effects.loop_filter(segment.duration) {
+            graph += &format!(",{filter}");
+        }
+        if !segment.text.is_empty() {
+            graph += &format!(",{}", drawtext_filter(&segment.text, fontsize));
+        }
+        graph += &format!(" [v{i}];");
+    }
+
+    let labels: Vec<String> = (0..segments.len()).map(|i| format!("[v{i}]")).collect();
+    let durations: Vec<f64> = segments.iter().map(Segment::output_duration).collect();
+    let transitions: Vec<Transition> = segments
+        .iter()
+        .skip(1)
+        .map(|segment| segment.transition)
+        .collect();
+    graph += &join(&labels, &durations, &transitions);
+    if let Some(gif) = gif {
+        graph += &format!(",{}", gif.palette_filter());
+    }
+    graph += " [out]";
+    graph
 }
 
-/// Stitches the `first_file` and the `second_file` together to create the `output_file`.
-///
-/// It uses the ffmpeg complex_filter "concat" with `unsafe=true` meaning that the resolution of
-/// the two files doesn't need to match and the `second_file` gets stretched to the resolution
-/// of the `first_file`.
+/// One step of re-encoding a file to make it smaller, see [`SHRINK_STEPS`].
+#[derive(Debug, Clone, Copy)]
+pub struct ShrinkStep {
+    /// The constant rate factor on the scale of VP9, higher values mean a lower bitrate
+    pub crf: u8,
+    /// The frame rate to reduce to, `None` keeps the original frame rate
+    pub fps: Option<u32>,
+    /// The factor the resolution is scaled by
+    pub scale: f32,
+    /// The maximum number of colors in the palette, only used for GIFs
+    pub max_colors: u32,
+}
+
+/// The steps tried one after another to shrink a file, each one smaller than the last.
+pub const SHRINK_STEPS: [ShrinkStep; 4] = [
+    ShrinkStep {
+        crf: 36,
+        fps: None,
+        scale: 1.0,
+        max_colors: 256,
+    },
+    ShrinkStep {
+        crf: 42,
+        fps: Some(15),
+        scale: 1.0,
+        max_colors: 128,
+    },
+    ShrinkStep {
+        crf: 48,
+        fps: Some(12),
+        scale: 0.75,
+        max_colors: 64,
+    },
+    ShrinkStep {
+        crf: 54,
+        fps: Some(10),
+        scale: 0.5,
+        max_colors: 32,
+    },
+];
+
+/// Re-encodes the `input_file` with the settings of the `step` to make it smaller and saves it
+/// to the `output_file`.
 ///
-/// For faster concatenation use the [`stitch_files_concat_demuxer()`] function.
+/// The file is encoded to the `format`, GIFs use the `gif` options with at most the
+/// `max_colors` and `fps` of the step, all other formats use the `crf` of the step.
+/// `on_progress` is called for every progress update of ffmpeg.
 ///
 /// # Errors
 ///
-/// This function will return an error if there is a problem with spawning the command.
-pub fn stitch_files(
-    first_file: &Path,
-    second_file: &Path,
+/// This function will return an error if the ffmpeg command could not be run or failed.
+pub fn shrink(
+    input_file: &Path,
+    step: &ShrinkStep,
+    format: OutputFormat,
+    gif: &GifOptions,
     output_file: &Path,
+    on_progress: impl FnMut(&ProgressEvent),
 ) -> Result<()> {
-    Command::new("ffmpeg")
-        .arg("-i")
-        .arg(first_file)
-        .arg("-i")
-        .arg(second_file)
-        .args([
-            "-filter_complex",
-            "[0:v] [1:v] concat=n=2:v=1:unsafe=true [v]",
-        ])
-        .args(["-map", "[v]"])
-        .arg(output_file)
-        .arg("-y")
-        // .stderr(Stdio::inherit())
-        .output()?;
-    Ok(())
-}
-
-/// Concatenates the two files using the ffmpeg concat demuxer -> faster than the concat
-/// complex_filter used by [`stitch_files()`]
-///
-/// # Panics
-///
-/// Panics if the parent dir for the `concat_list.txt` can not be found.
+    shrink_command(input_file, step, format, gif, output_file).run_with_progress(on_progress)
+}
+
+/// Re-encodes the `input_file` like [`shrink()`] without blocking the async runtime.
 ///
 /// # Errors
 ///
-/// This function will return an error if there is a problem with creating the `concat_list.txt` file
-/// or if the ffmpeg command cannot be run.
-// Reference: https://stackoverflow.com/a/11175851/10018101
-pub fn stitch_files_concat_demuxer(
-    first_file: &Path,
-    second_file: &Path,
+/// This function will return an error if the ffmpeg command could not be run or failed.
+#[cfg(feature = "async")]
+pub async fn shrink_async(
+    input_file: &Path,
+    step: &ShrinkStep,
+    format: OutputFormat,
+    gif: &GifOptions,
     output_file: &Path,
+    on_progress: impl FnMut(&ProgressEvent),
 ) -> Result<()> {
-    let file_list_text = format!(
-        "file '{}'\nfile '{}'",
-        first_file.as_os_str().to_string_lossy(),
-        second_file.as_os_str().to_string_lossy()
-    );
-
-    let concat_list_path = temp_dir().join("time-for\\concat_list.txt");
-    let parent_path = concat_list_path
-        .parent()
-        .ok_or(temp_dir().join("time-for").as_path())
-        .expect("Get parent path");
-    fs::create_dir_all(parent_path)?;
-    let mut list_file = File::create(temp_dir().join("time-for\\concat_list.txt"))?;
-    write!(list_file, "{}", file_list_text)?;
-
-    Command::new("ffmpeg")
-        .arg("-safe")
-        .arg("0")
-        .arg("-f")
-        .arg("concat")
-        .arg("-i")
-        .arg(concat_list_path)
-        .arg("-c")
-        .arg("copy")
-        .arg("-y")
-        .arg(output_file)
-        // .stderr(Stdio::inherit())
-        .output()?;
-    Ok(())
+    shrink_command(input_file, step, format, gif, output_file)
+        .run_with_progress_async(on_progress)
+        .await
+}
+
+/// The command of [`shrink()`].
+fn shrink_command(
+    input_file: &Path,
+    step: &ShrinkStep,
+    format: OutputFormat,
+    gif: &GifOptions,
+    output_file: &Path,
+) -> FfmpegCommand {
+    let mut filters = vec![];
+    if let Some(fps) = step.fps.filter(|_| !format.needs_palette()) {
+        filters.push(format!("fps={}", fps));
+    }
+    if step.scale < 1.0 {
+        filters.push(format!(
+            "scale=trunc(iw*{0}/2)*2:trunc(ih*{0}/2)*2",
+            step.scale
+        ));
+    }
+    let filters = if filters.is_empty() {
+        "null".to_string()
+    } else {
+        filters.join(",")
+    };
+
+    let command = FfmpegCommand::new().input(input_file);
+    let command = if format.needs_palette() {
+        let gif = GifOptions {
+            fps: match (gif.fps, step.fps) {
+                (Some(fps), Some(step_fps)) => Some(fps.min(step_fps)),
+                (fps, step_fps) => fps.or(step_fps),
+            },
+            max_colors: gif.max_colors.min(step.max_colors),
+            ..gif.clone()
+        };
+        command.filter_complex(&format!("[0:v] {},{}", filters, gif.palette_filter()))
+    } else {
+        command.video_filter(&filters)
+    };
+
+    command
+        .args(format.encoder_args(Some(step.crf)))
+        .no_audio()
+        .output(output_file)
+}
+
+/// Stitches the `first_file` and the `second_file` together to create the `output_file`.
+///
+/// It uses the ffmpeg complex_filter "concat" with `unsafe=true` meaning that the resolution of
+/// the two files doesn't need to match and the `second_file` gets stretched to the resolution
+/// of the `first_file`.
+///
+/// # Errors
+///
+/// This function will return an error if the ffmpeg command could not be run or failed.
+pub fn stitch_files(first_file: &Path, second_file: &Path, output_file: &Path) -> Result<()> {
+    FfmpegCommand::new()
+        .input(first_file)
+        .input(second_file)
+        .filter_complex("[0:v] [1:v] concat=n=2:v=1:unsafe=true [v]")
+        .map("[v]")
+        .output(output_file)
+        .run()
 }
 
 /// Check whether or not the `ffmpeg` command can be run
@@ -183,6 +417,16 @@ pub enum FfmpegError {
     NotFound,
     #[error("Unknown Io error")]
     Io { source: io::Error },
+    #[error(
+        "ffmpeg exited with code {}\n  ffmpeg {}\n{stderr_tail}",
+        exit_code.map_or("None".to_string(), |c| c.to_string()),
+        args.join(" ")
+    )]
+    Failed {
+        args: Vec<String>,
+        exit_code: Option<i32>,
+        stderr_tail: String,
+    },
 }
 
 impl From<io::Error> for FfmpegError {
@@ -195,3 +439,45 @@ impl From<io::Error> for FfmpegError {
 }
 
 type Result<T> = std::result::Result<T, FfmpegError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `text=` option of the drawtext filter, still escaped for the filter graph.
+    fn drawn_text(text: &str) -> String {
+        let filter = drawtext_filter(text, 22);
+        let (_, text) = filter.split_once(":text=").unwrap();
+        text.strip_suffix('\'').unwrap().to_string()
+    }
+
+    #[test]
+    fn escapes_colons_in_the_text() {
+        assert_eq!(drawn_text("12:30"), r"12\:30");
+    }
+
+    #[test]
+    fn escapes_apostrophes_in_the_text() {
+        // `\'` for the options, with the `'` closing, escaped and reopening the graph quote
+        assert_eq!(drawn_text("it's"), r"it\'\''s");
+    }
+
+    #[test]
+    fn escapes_backslashes_in_the_text() {
+        assert_eq!(drawn_text(r"a\b"), r"a\\b");
+    }
+
+    #[test]
+    fn draws_percent_signs_as_is() {
+        let filter = drawtext_filter("100%", 22);
+        assert!(filter.contains(":expansion=none:"));
+        assert!(filter.ends_with(":text=100%'"));
+    }
+
+    #[test]
+    fn quotes_the_whole_filter() {
+        let filter = drawtext_filter("time for tea", 22);
+        assert!(filter.starts_with("drawtext='fontfile="));
+        assert!(filter.ends_with(":text=time for tea'"));
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    ffi::{OsStr, OsString},
+    io::{BufRead, BufReader, Read},
+    process::{Command, ExitStatus, Stdio},
+    thread,
+};
+
+use super::{FfmpegError, ProgressEvent, ProgressParser, Result};
+
+/// The number of lines of the stderr output kept for [`FfmpegError::Failed`].
+const STDERR_TAIL_LINES: usize = 20;
+
+/// A builder for an ffmpeg command which records all its arguments, so a failing command can
+/// be reported together with the arguments it was run with and the end of its stderr output.
+///
+/// The arguments are added in the order ffmpeg expects them, meaning input options have to be
+/// added before their [`input()`](FfmpegCommand::input) and output options before the
+/// [`output()`](FfmpegCommand::output).
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use time_for::ffmpeg::FfmpegCommand;
+/// FfmpegCommand::new()
+///     .input(Path::new("in.webm"))
+///     .video_filter("scale=480:270")
+///     .output(Path::new("out.webm"))
+///     .run()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct FfmpegCommand {
+    args: Vec<OsString>,
+}
+
+impl FfmpegCommand {
+    /// Creates a command which overwrites its output files without asking and doesn't print
+    /// the banner or encoding statistics, so the stderr output only contains the relevant
+    /// information.
+    pub fn new() -> Self {
+        Self {
+            args: ["-hide_banner", "-nostats", "-nostdin", "-y"]
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        }
+    }
+
+    /// Adds a single argument.
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    /// Adds multiple arguments.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_os_string()));
+        self
+    }
+
+    /// Adds an input file (`-i <file>`).
+    pub fn input(self, file: impl AsRef<OsStr>) -> Self {
+        self.arg("-i").arg(file)
+    }
+
+    /// Sets the simple filter graph for the video stream (`-vf <filter>`).
+    pub fn video_filter(self, filter: &str) -> Self {
+        self.args(["-vf", filter])
+    }
+
+    /// Sets the complex filter graph (`-filter_complex <graph>`).
+    pub fn filter_complex(self, graph: &str) -> Self {
+        self.args(["-filter_complex", graph])
+    }
+
+    /// Maps a stream or filter graph output into the output file (`-map <stream>`).
+    pub fn map(self, stream: &str) -> Self {
+        self.args(["-map", stream])
+    }
+
+    /// Sets the codec for all streams (`-c <codec>`), `copy` copies the streams without
+    /// re-encoding.
+    pub fn codec(self, codec: &str) -> Self {
+        self.args(["-c", codec])
+    }
+
+    /// Sets the video codec (`-c:v <codec>`).
+    pub fn video_codec(self, codec: &str) -> Self {
+        self.args(["-c:v", codec])
+    }
+
+    /// Removes the audio streams (`-an`).
+    pub fn no_audio(self) -> Self {
+        self.arg("-an")
+    }
+
+    /// Adds the output file, which has to come after all output options.
+    pub fn output(self, file: impl AsRef<OsStr>) -> Self {
+        self.arg(file)
+    }
+
+    /// All arguments the command is run with.
+    pub fn get_args(&self) -> &[OsString] {
+        &self.args
+    }
+
+    /// Runs the command and waits for it to finish.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if ffmpeg could not be run or exited unsuccessfully.
+    pub fn run(&self) -> Result<()> {
+        let output = Command::new("ffmpeg")
+            .args(&self.args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()?;
+        check_status(
+            output.status,
+            &String::from_utf8_lossy(&output.stderr),
+            self.args_lossy(),
+        )
+    }
+
+    /// Runs the command with `-progress pipe:1` and calls `on_progress` for every progress
+    /// update ffmpeg reports until it is finished.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if ffmpeg could not be run or exited unsuccessfully.
+    pub fn run_with_progress(&self, mut on_progress: impl FnMut(&ProgressEvent)) -> Result<()> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-progress", "pipe:1"])
+            .args(&self.args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Read stderr on its own thread, so a full pipe can't block ffmpeg while the progress
+        // is read from stdout
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr_reader = thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output);
+            output
+        });
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut parser = ProgressParser::default();
+        for line in BufReader::new(stdout).lines() {
+            if let Some(event) = parser.parse_line(&line?) {
+                on_progress(&event);
+            }
+        }
+
+        let status = child.wait()?;
+        let stderr = stderr_reader.join().unwrap_or_default();
+        check_status(status, &stderr, self.args_lossy())
+    }
+
+    /// Runs the command like [`run_with_progress()`](FfmpegCommand::run_with_progress)
+    /// without blocking the async runtime.
+    ///
+    /// ffmpeg is killed if the returned future is dropped before it finished.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if ffmpeg could not be run or exited unsuccessfully.
+    #[cfg(feature = "async")]
+    pub async fn run_with_progress_async(
+        &self,
+        mut on_progress: impl FnMut(&ProgressEvent),
+    ) -> Result<()> {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+        let mut child = tokio::process::Command::new("ffmpeg")
+            .args(["-progress", "pipe:1"])
+            .args(&self.args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        // Read stderr at the same time, so a full pipe can't block ffmpeg while the progress is
+        // read from stdout
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr_reader = async move {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output).await;
+            output
+        };
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let progress_reader = async move {
+            let mut parser = ProgressParser::default();
+            let mut lines = tokio::io::BufReader::new(stdout).lines();
+            while let Some(line) = lines.next_line().await? {
+                if let Some(event) = parser.parse_line(&line) {
+                    on_progress(&event);
+                }
+            }
+            Ok::<_, std::io::Error>(())
+        };
+
+        let (progress, stderr) = futures_util::future::join(progress_reader, stderr_reader).await;
+        progress?;
+        let status = child.wait().await?;
+        check_status(status, &stderr, self.args_lossy())
+    }
+
+    fn args_lossy(&self) -> Vec<String> {
+        self.args
+            .iter()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect()
+    }
+}
+
+impl Default for FfmpegCommand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turns an unsuccessful exit `status` into [`FfmpegError::Failed`] with the end of the
+/// `stderr` output.
+fn check_status(status: ExitStatus, stderr: &str, args: Vec<String>) -> Result<()> {
+    if status.success() {
+        return Ok(());
+    }
+
+    let lines: Vec<&str> = stderr.lines().collect();
+    let stderr_tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+
+    Err(FfmpegError::Failed {
+        args,
+        exit_code: status.code(),
+        stderr_tail,
+    })
+}
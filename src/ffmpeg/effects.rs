@@ -0,0 +1,160 @@
+/// Changes to the playback of a clip, applied while normalizing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Effects {
+    /// The factor the clip is sped up by, e.g. `2.0` plays it twice as fast
+    pub speed: f64,
+    /// Play the clip backwards
+    pub reverse: bool,
+    /// Play the clip forwards and then backwards
+    pub boomerang: bool,
+    /// Repeat the clip until it is at least this long in seconds
+    pub min_duration: Option<f64>,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            reverse: false,
+            boomerang: false,
+            min_duration: None,
+        }
+    }
+}
+
+impl Effects {
+    /// The duration in seconds a clip with the `duration` has after applying the effects.
+    pub fn duration(&self, duration: f64) -> f64 {
+        let played = self.played_duration(duration);
+        played * self.loops(played) as f64
+    }
+
+    /// The duration of a single playback of a clip with the `duration`, before looping it.
+    fn played_duration(&self, duration: f64) -> f64 {
+        let duration = duration / self.speed;
+        match self.boomerang {
+            true => duration * 2.0,
+            false => duration,
+        }
+    }
+
+    /// How often a clip with the `played` duration is played to reach the `min_duration`.
+    fn loops(&self, played: f64) -> u32 {
+        match self.min_duration {
+            Some(min_duration) if played > 0.0 => (min_duration / played).ceil().max(1.0) as u32,
+            _ => 1,
+        }
+    }
+
+    /// The filters changing the speed and direction of the clip `i`, applied before
+    /// normalizing it. `None` if there is nothing to change.
+    ///
+    /// Reversing buffers the whole clip in memory, which is fine for GIF sized clips.
+    pub fn filter(&self, i: usize) -> Option<String> {
+        let mut filters = vec![];
+        if self.speed != 1.0 {
+            filters.push(format!("setpts=(PTS-STARTPTS)/{}", self.speed));
+        }
+        if self.reverse {
+            filters.push("reverse".to_string());
+        }
+        if self.boomerang {
+            filters.push(format!(
+                "split [fwd{i}][bwd{i}];[bwd{i}] reverse [rev{i}];\
+                 [fwd{i}][rev{i}] concat=n=2:v=1:a=0"
+            ));
+        }
+        (!filters.is_empty()).then(|| filters.join(","))
+    }
+
+    /// The filter repeating a clip with the `duration` in seconds (before applying the
+    /// effects) to reach the `min_duration`, applied after normalizing it, since it needs a
+    /// constant frame rate. `None` if the clip is long enough.
+    pub fn loop_filter(&self, duration: f64) -> Option<String> {
+        let loops = self.loops(self.played_duration(duration));
+        // The size is the maximum number of frames the loop filter can repeat
+        (loops > 1).then(|| format!("loop=loop={}:size=32767,setpts=N/FRAME_RATE/TB", loops - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_without_effects() {
+        assert_eq!(Effects::default().duration(2.5), 2.5);
+    }
+
+    #[test]
+    fn duration_with_speed() {
+        let effects = Effects {
+            speed: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(effects.duration(3.0), 1.5);
+        let effects = Effects {
+            speed: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(effects.duration(3.0), 6.0);
+    }
+
+    #[test]
+    fn reverse_keeps_the_duration() {
+        let effects = Effects {
+            reverse: true,
+            ..Default::default()
+        };
+        assert_eq!(effects.duration(3.0), 3.0);
+    }
+
+    #[test]
+    fn boomerang_doubles_the_duration() {
+        let effects = Effects {
+            speed: 2.0,
+            boomerang: true,
+            ..Default::default()
+        };
+        assert_eq!(effects.duration(3.0), 3.0);
+    }
+
+    #[test]
+    fn min_duration_loops_whole_playbacks() {
+        let effects = Effects {
+            min_duration: Some(5.0),
+            ..Default::default()
+        };
+        // 2s are played three times to be at least 5s long
+        assert_eq!(effects.duration(2.0), 6.0);
+        assert_eq!(effects.duration(2.5), 5.0);
+        assert_eq!(effects.duration(8.0), 8.0);
+        assert_eq!(
+            effects.loop_filter(2.0).as_deref(),
+            Some("loop=loop=2:size=32767,setpts=N/FRAME_RATE/TB")
+        );
+        assert_eq!(effects.loop_filter(8.0), None);
+    }
+
+    #[test]
+    fn min_duration_applies_after_the_other_effects() {
+        let effects = Effects {
+            speed: 2.0,
+            boomerang: true,
+            min_duration: Some(5.0),
+            ..Default::default()
+        };
+        // Every playback is 2 / 2 * 2 = 2s long
+        assert_eq!(effects.duration(2.0), 6.0);
+    }
+
+    #[test]
+    fn empty_clips_are_not_looped() {
+        let effects = Effects {
+            min_duration: Some(5.0),
+            ..Default::default()
+        };
+        assert_eq!(effects.duration(0.0), 0.0);
+        assert_eq!(effects.loop_filter(0.0), None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+
+use clap::ValueEnum;
+
+/// The file formats the final clip can be encoded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// An actual GIF with a generated palette, large but supported everywhere
+    Gif,
+    /// VP9 video, small and fast to encode
+    #[default]
+    Webm,
+    /// H.264 video, the most widely supported video format
+    Mp4,
+    /// Animated WebP image
+    Webp,
+    /// Animated PNG image, lossless
+    Apng,
+    /// Animated AVIF image (AV1), the smallest but slowest to encode
+    Avif,
+}
+
+impl OutputFormat {
+    /// The file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Gif => "gif",
+            OutputFormat::Webm => "webm",
+            OutputFormat::Mp4 => "mp4",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Apng => "png",
+            OutputFormat::Avif => "avif",
+        }
+    }
+
+    /// The mime type of the format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Gif => "image/gif",
+            OutputFormat::Webm => "video/webm",
+            OutputFormat::Mp4 => "video/mp4",
+            OutputFormat::Webp => "image/webp",
+            OutputFormat::Apng => "image/apng",
+            OutputFormat::Avif => "image/avif",
+        }
+    }
+
+    /// Whether the format is a video (as opposed to an animated image).
+    pub fn is_video(&self) -> bool {
+        matches!(self, OutputFormat::Webm | OutputFormat::Mp4)
+    }
+
+    /// Whether the format needs a palette generated from the clip.
+    pub fn needs_palette(&self) -> bool {
+        *self == OutputFormat::Gif
+    }
+
+    /// The output options to encode the format with.
+    ///
+    /// The `crf` is the constant rate factor on the scale of VP9 and AV1 (0-63, lower is
+    /// better), which is converted to the scale of the other encoders. Without a `crf` the
+    /// default quality of the format is used. GIF and APNG have no quality setting.
+    pub fn encoder_args(&self, crf: Option<u8>) -> Vec<String> {
+        let args = match self {
+            OutputFormat::Gif => "-f gif -loop 0".to_string(),
+            OutputFormat::Webm => format!(
+                "-c:v libvpx-vp9 -b:v 0 -crf {} -deadline realtime -cpu-used 8",
+                crf.unwrap_or(32)
+            ),
+            OutputFormat::Mp4 => format!(
+                "-c:v libx264 -preset veryfast -crf {} -pix_fmt yuv420p -movflags +faststart",
+                // x264 uses a scale of 0-51
+                crf.map_or(23, |crf| crf as u32 * 51 / 63)
+            ),
+            OutputFormat::Webp => format!(
+                "-c:v libwebp -q:v {} -loop 0",
+                // libwebp uses a quality of 0-100, higher is better
+                crf.map_or(75, |crf| 100 - (crf as u32 * 100 / 63).min(100))
+            ),
+            OutputFormat::Apng => "-f apng -plays 0".to_string(),
+            OutputFormat::Avif => format!(
+                "-c:v libaom-av1 -b:v 0 -crf {} -cpu-used 8 -row-mt 1",
+                crf.unwrap_or(35)
+            ),
+        };
+        args.split(' ').map(String::from).collect()
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Apng => write!(f, "apng"),
+            format => write!(f, "{}", format.extension()),
+        }
+    }
+}
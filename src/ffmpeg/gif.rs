@@ -0,0 +1,209 @@
+use clap::ValueEnum;
+
+/// A preset trading the file size of a GIF against its quality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum GifQuality {
+    /// Fewer frames and colors with lossy compression, for size limited targets like Discord
+    Small,
+    /// Full colors at a reduced frame rate
+    #[default]
+    Balanced,
+    /// The frame rate of the clip with error diffusion dithering
+    Best,
+}
+
+impl GifQuality {
+    /// The encoding options of the preset.
+    pub fn options(&self) -> GifOptions {
+        match self {
+            GifQuality::Small => GifOptions {
+                fps: Some(12),
+                max_colors: 128,
+                dither: Dither::Bayer,
+                stats_mode: StatsMode::Diff,
+                per_frame_palette: false,
+                lossy: Some(80),
+            },
+            GifQuality::Balanced => GifOptions {
+                fps: Some(15),
+                max_colors: 256,
+                dither: Dither::Sierra2_4a,
+                stats_mode: StatsMode::Full,
+                per_frame_palette: false,
+                lossy: None,
+            },
+            GifQuality::Best => GifOptions {
+                fps: None,
+                max_colors: 256,
+                dither: Dither::FloydSteinberg,
+                stats_mode: StatsMode::Full,
+                per_frame_palette: false,
+                lossy: None,
+            },
+        }
+    }
+}
+
+/// The dithering algorithm `paletteuse` uses to approximate colors missing in the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Dither {
+    /// No dithering, which gives flat areas of color and the smallest files
+    None,
+    /// An ordered 8x8 pattern, compresses well
+    Bayer,
+    Heckbert,
+    FloydSteinberg,
+    Sierra2,
+    #[value(name = "sierra2_4a")]
+    Sierra2_4a,
+    Sierra3,
+    Burkes,
+}
+
+impl Dither {
+    /// The name of the algorithm as used by `paletteuse`.
+    fn filter_value(&self) -> &'static str {
+        match self {
+            Dither::None => "none",
+            // A higher scale makes the pattern less visible and the file smaller
+            Dither::Bayer => "bayer:bayer_scale=3",
+            Dither::Heckbert => "heckbert",
+            Dither::FloydSteinberg => "floyd_steinberg",
+            Dither::Sierra2 => "sierra2",
+            Dither::Sierra2_4a => "sierra2_4a",
+            Dither::Sierra3 => "sierra3",
+            Dither::Burkes => "burkes",
+        }
+    }
+}
+
+/// Which pixels `palettegen` counts to pick the colors of the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatsMode {
+    /// All pixels of every frame
+    Full,
+    /// Only the pixels that change between frames, which favors moving parts over a static
+    /// background
+    Diff,
+}
+
+impl StatsMode {
+    fn filter_value(&self) -> &'static str {
+        match self {
+            StatsMode::Full => "full",
+            StatsMode::Diff => "diff",
+        }
+    }
+}
+
+/// The settings used to encode a GIF, usually created from a [`GifQuality`] preset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GifOptions {
+    /// The frame rate to reduce to, `None` keeps the frame rate of the clip
+    pub fps: Option<u32>,
+    /// The maximum number of colors in a palette, at most 256
+    pub max_colors: u32,
+    pub dither: Dither,
+    pub stats_mode: StatsMode,
+    /// Generate a palette for every frame instead of one for the whole clip, which gives
+    /// better colors for clips with changing scenes but larger files
+    pub per_frame_palette: bool,
+    /// The lossy compression level of gifsicle (usually 30-200), `None` disables it
+    pub lossy: Option<u32>,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifQuality::default().options()
+    }
+}
+
+impl GifOptions {
+    /// The filter chain which reduces the frame rate, generates the palette(s) and uses them,
+    /// to be appended to a filter graph with a single video stream.
+    pub fn palette_filter(&self) -> String {
+        let mut filter = String::new();
+        if let Some(fps) = self.fps {
+            filter += &format!("fps={fps},");
+        }
+
+        let (stats_mode, new) = match self.per_frame_palette {
+            true => ("single", ":new=1"),
+            false => (self.stats_mode.filter_value(), ""),
+        };
+        filter += &format!(
+            "split [a][b];[a] palettegen=max_colors={}:stats_mode={} [p];\
+             [b][p] paletteuse=dither={}{}",
+            self.max_colors.clamp(2, 256),
+            stats_mode,
+            self.dither.filter_value(),
+            new,
+        );
+        // Only update the changed parts of a frame with the diff palette, which keeps a static
+        // background from flickering
+        if self.stats_mode == StatsMode::Diff && !self.per_frame_palette {
+            filter += ":diff_mode=rectangle";
+        }
+        filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_filter_of_the_balanced_preset() {
+        assert_eq!(
+            GifQuality::Balanced.options().palette_filter(),
+            "fps=15,split [a][b];[a] palettegen=max_colors=256:stats_mode=full [p];\
+             [b][p] paletteuse=dither=sierra2_4a"
+        );
+    }
+
+    #[test]
+    fn palette_filter_of_the_small_preset() {
+        assert_eq!(
+            GifQuality::Small.options().palette_filter(),
+            "fps=12,split [a][b];[a] palettegen=max_colors=128:stats_mode=diff [p];\
+             [b][p] paletteuse=dither=bayer:bayer_scale=3:diff_mode=rectangle"
+        );
+    }
+
+    #[test]
+    fn palette_filter_keeps_the_frame_rate_without_fps() {
+        assert_eq!(
+            GifQuality::Best.options().palette_filter(),
+            "split [a][b];[a] palettegen=max_colors=256:stats_mode=full [p];\
+             [b][p] paletteuse=dither=floyd_steinberg"
+        );
+    }
+
+    #[test]
+    fn palette_filter_with_a_palette_per_frame() {
+        let gif = GifOptions {
+            per_frame_palette: true,
+            ..GifQuality::Small.options()
+        };
+        assert_eq!(
+            gif.palette_filter(),
+            "fps=12,split [a][b];[a] palettegen=max_colors=128:stats_mode=single [p];\
+             [b][p] paletteuse=dither=bayer:bayer_scale=3:new=1"
+        );
+    }
+
+    #[test]
+    fn palette_filter_clamps_the_colors() {
+        for (max_colors, clamped) in [(0, 2), (1, 2), (64, 64), (1000, 256)] {
+            let gif = GifOptions {
+                max_colors,
+                ..Default::default()
+            };
+            assert!(
+                gif.palette_filter()
+                    .contains(&format!("palettegen=max_colors={clamped}:")),
+                "{max_colors}"
+            );
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+/// The progress of a running ffmpeg command, as reported by `-progress`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgressEvent {
+    /// The number of frames written so far
+    pub frame: u64,
+    /// The timestamp of the output written so far
+    pub out_time: Duration,
+    /// The encoding speed relative to real time, e.g. `2.5` for `2.5x`
+    pub speed: Option<f64>,
+    /// Whether this is the last event of the command
+    pub done: bool,
+}
+
+/// Collects the `key=value` lines ffmpeg writes with `-progress` into [`ProgressEvent`]s.
+///
+/// ffmpeg writes a block of lines for every update, which ends with `progress=continue` or
+/// `progress=end`.
+#[derive(Debug, Default)]
+pub struct ProgressParser {
+    current: ProgressEvent,
+}
+
+impl ProgressParser {
+    /// Parses the next `line`, returning the event once its block is complete.
+    pub fn parse_line(&mut self, line: &str) -> Option<ProgressEvent> {
+        let (key, value) = line.trim().split_once('=')?;
+        let value = value.trim();
+        match key {
+            "frame" => self.current.frame = value.parse().unwrap_or(self.current.frame),
+            // Despite its name `out_time_ms` is in microseconds as well
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(us) = value.parse::<u64>() {
+                    self.current.out_time = Duration::from_micros(us);
+                }
+            }
+            "speed" => self.current.speed = value.trim_end_matches('x').parse().ok(),
+            "progress" => {
+                self.current.done = value == "end";
+                return Some(self.current.clone());
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(parser: &mut ProgressParser, block: &str) -> Vec<ProgressEvent> {
+        block
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .collect()
+    }
+
+    #[test]
+    fn returns_an_event_for_every_block() {
+        let mut parser = ProgressParser::default();
+        let events = parse(
+            &mut parser,
+            "frame=10\nfps=25.0\nout_time_us=400000\nout_time=00:00:00.400000\nspeed=2.5x\n\
+             progress=continue\n\
+             frame=20\nout_time_us=800000\nspeed=3x\nprogress=end\n",
+        );
+        assert_eq!(
+            events,
+            [
+                ProgressEvent {
+                    frame: 10,
+                    out_time: Duration::from_millis(400),
+                    speed: Some(2.5),
+                    done: false,
+                },
+                ProgressEvent {
+                    frame: 20,
+                    out_time: Duration::from_millis(800),
+                    speed: Some(3.0),
+                    done: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_out_time_ms_as_microseconds() {
+        let mut parser = ProgressParser::default();
+        let events = parse(&mut parser, "out_time_ms=1500000\nprogress=continue\n");
+        assert_eq!(events[0].out_time, Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn keeps_the_last_values_for_unknown_ones() {
+        let mut parser = ProgressParser::default();
+        let events = parse(
+            &mut parser,
+            "frame=5\nout_time_us=200000\nspeed=1x\nprogress=continue\n\
+             frame=N/A\nout_time_us=N/A\nspeed=N/A\nprogress=continue\n",
+        );
+        assert_eq!(events[1].frame, 5);
+        assert_eq!(events[1].out_time, Duration::from_millis(200));
+        assert_eq!(events[1].speed, None);
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        let mut parser = ProgressParser::default();
+        assert_eq!(parser.parse_line(""), None);
+        assert_eq!(parser.parse_line("not progress"), None);
+        assert_eq!(parser.parse_line("bitrate=1024.0kbits/s"), None);
+        assert_eq!(parser.parse_line("frame=3"), None);
+        assert_eq!(
+            parser.parse_line("progress=end\r"),
+            Some(ProgressEvent {
+                frame: 3,
+                done: true,
+                ..Default::default()
+            })
+        );
+    }
+}
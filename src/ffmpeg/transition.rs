@@ -0,0 +1,201 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+/// How one clip changes to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransitionKind {
+    /// A hard cut without any transition
+    #[default]
+    Cut,
+    /// The clips fade into each other
+    Crossfade,
+    /// The next clip is revealed from right to left
+    Wipe,
+    /// The next clip slides in from the right, pushing out the previous one
+    Slide,
+    /// The clip fades to white and the next one fades in from white
+    Flash,
+}
+
+impl TransitionKind {
+    /// The name of the transition as used by the `xfade` filter, `None` for a cut.
+    fn xfade_name(&self) -> Option<&'static str> {
+        match self {
+            TransitionKind::Cut => None,
+            TransitionKind::Crossfade => Some("fade"),
+            TransitionKind::Wipe => Some("wipeleft"),
+            TransitionKind::Slide => Some("slideleft"),
+            TransitionKind::Flash => Some("fadewhite"),
+        }
+    }
+}
+
+/// The transition between all clips together with how long it takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    /// The duration in seconds
+    pub duration: f64,
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Self {
+            kind: TransitionKind::Cut,
+            duration: 0.5,
+        }
+    }
+}
+
+impl Transition {
+    /// A hard cut.
+    pub fn cut() -> Self {
+        Self {
+            kind: TransitionKind::Cut,
+            ..Default::default()
+        }
+    }
+
+    /// How long the clips with the durations `before` and `after` (in seconds) overlap during
+    /// the transition between them.
+    ///
+    /// The transition is shortened to at most half of the shorter clip. A cut takes no time.
+    pub fn overlap(&self, before: f64, after: f64) -> f64 {
+        match self.kind {
+            TransitionKind::Cut => 0.0,
+            _ => self.duration.clamp(0.0, before.min(after).max(0.0) / 2.0),
+        }
+    }
+}
+
+/// The filter graph joining the streams with the `labels` and the `durations` in seconds,
+/// without an output label, so more filters can be appended.
+///
+/// `transitions[i]` is the transition from the clip `i` to the clip `i + 1`, missing ones are
+/// cuts. Consecutive cuts are joined with `concat`. For three clips with a crossfade and a cut
+/// it looks like this:
+///
+/// ```text
+/// [v0][v1] xfade=transition=fade:duration=0.500:offset=2.500 [x1];
+/// [x1][v2] concat=n=2:v=1:a=0
+/// ```
+pub fn join(labels: &[String], durations: &[f64], transitions: &[Transition]) -> String {
+    if transitions
+        .iter()
+        .all(|transition| transition.kind == TransitionKind::Cut)
+    {
+        return format!("{} concat=n={}:v=1:a=0", labels.concat(), labels.len());
+    }
+
+    let mut graph = String::new();
+    let mut previous = labels[0].clone();
+    // The duration of the joined stream so far
+    let mut end = durations[0];
+    for (i, label) in labels.iter().enumerate().skip(1) {
+        if i > 1 {
+            graph += &format!(" [x{}];", i - 1);
+        }
+        let transition = transitions
+            .get(i - 1)
+            .copied()
+            .unwrap_or_else(Transition::cut);
+        let overlap = transition.overlap(durations[i - 1], durations[i]);
+        graph += &match transition.kind.xfade_name() {
+            Some(xfade) if overlap > 0.0 => format!(
+                "{previous}{label} xfade=transition={xfade}:duration={overlap:.3}:offset={:.3}",
+                end - overlap
+            ),
+            _ => format!("{previous}{label} concat=n=2:v=1:a=0"),
+        };
+        previous = format!("[x{i}]");
+        end += durations[i] - overlap;
+    }
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("[v{i}]")).collect()
+    }
+
+    fn crossfade(duration: f64) -> Transition {
+        Transition {
+            kind: TransitionKind::Crossfade,
+            duration,
+        }
+    }
+
+    #[test]
+    fn cuts_take_no_time() {
+        assert_eq!(Transition::cut().overlap(3.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn overlap_is_at_most_half_of_the_shorter_clip() {
+        assert_eq!(crossfade(0.5).overlap(3.0, 2.0), 0.5);
+        assert_eq!(crossfade(2.0).overlap(3.0, 2.0), 1.0);
+        assert_eq!(crossfade(2.0).overlap(0.6, 4.0), 0.3);
+        assert_eq!(crossfade(0.5).overlap(0.0, 2.0), 0.0);
+        assert_eq!(crossfade(-1.0).overlap(3.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn joins_only_cuts_with_one_concat() {
+        assert_eq!(
+            join(&labels(3), &[3.0, 2.0, 2.0], &[Transition::cut(); 2]),
+            "[v0][v1][v2] concat=n=3:v=1:a=0"
+        );
+        assert_eq!(
+            join(&labels(3), &[3.0, 2.0, 2.0], &[]),
+            "[v0][v1][v2] concat=n=3:v=1:a=0"
+        );
+    }
+
+    #[test]
+    fn joins_transitions_and_cuts_one_after_another() {
+        assert_eq!(
+            join(
+                &labels(3),
+                &[3.0, 2.0, 2.0],
+                &[crossfade(0.5), Transition::cut()]
+            ),
+            "[v0][v1] xfade=transition=fade:duration=0.500:offset=2.500 [x1];\
+             [x1][v2] concat=n=2:v=1:a=0"
+        );
+    }
+
+    #[test]
+    fn offsets_start_where_the_joined_stream_ends() {
+        let wipe = Transition {
+            kind: TransitionKind::Wipe,
+            duration: 0.5,
+        };
+        // The first transition shortens the joined stream to 3 + 2 - 0.5 = 4.5s
+        assert_eq!(
+            join(&labels(3), &[3.0, 2.0, 2.0], &[crossfade(0.5), wipe]),
+            "[v0][v1] xfade=transition=fade:duration=0.500:offset=2.500 [x1];\
+             [x1][v2] xfade=transition=wipeleft:duration=0.500:offset=4.000"
+        );
+    }
+
+    #[test]
+    fn missing_transitions_are_cuts() {
+        assert_eq!(
+            join(&labels(3), &[3.0, 2.0, 2.0], &[crossfade(0.5)]),
+            "[v0][v1] xfade=transition=fade:duration=0.500:offset=2.500 [x1];\
+             [x1][v2] concat=n=2:v=1:a=0"
+        );
+    }
+
+    #[test]
+    fn transitions_without_overlap_are_cuts() {
+        assert_eq!(
+            join(&labels(2), &[3.0, 0.0], &[crossfade(0.5)]),
+            "[v0][v1] concat=n=2:v=1:a=0"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    ffi::OsStr,
+    fmt, io,
+    process::{Command, Output, Stdio},
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// The properties of the video stream of a media file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaInfo {
+    /// The duration in seconds
+    pub duration: f64,
+    pub width: u32,
+    pub height: u32,
+    /// The frames per second
+    pub fps: f64,
+    /// The name of the video codec, e.g. `vp9`
+    pub codec: String,
+    /// The pixel format, e.g. `yuv420p`
+    pub pixel_format: Option<String>,
+    /// Whether the file has an audio stream
+    pub has_audio: bool,
+}
+
+impl fmt::Display for MediaInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} {:.2}fps {:.2}s {}",
+            self.width, self.height, self.fps, self.duration, self.codec
+        )
+    }
+}
+
+/// Inspects the media file at `input` (a path or url) with ffprobe.
+///
+/// # Errors
+///
+/// This function will return an error if ffprobe could not be run or failed, its output
+/// could not be parsed or the file has no video stream.
+pub fn probe(input: impl AsRef<OsStr>) -> Result<MediaInfo, FfprobeError> {
+    let output = command(input.as_ref()).output()?;
+    parse_output(input.as_ref(), output)
+}
+
+/// Inspects the media file at `input` like [`probe()`] without blocking the async runtime.
+///
+/// # Errors
+///
+/// This function will return an error if ffprobe could not be run or failed, its output
+/// could not be parsed or the file has no video stream.
+#[cfg(feature = "async")]
+pub async fn probe_async(input: impl AsRef<OsStr>) -> Result<MediaInfo, FfprobeError> {
+    let output = tokio::process::Command::from(command(input.as_ref()))
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    parse_output(input.as_ref(), output)
+}
+
+/// The ffprobe command printing the format and streams of the `input` as json.
+fn command(input: &OsStr) -> Command {
+    let mut command = Command::new("ffprobe");
+    command
+        .args(["-v", "error", "-print_format", "json"])
+        .args(["-show_format", "-show_streams"])
+        .arg(input)
+        .stdin(Stdio::null());
+    command
+}
+
+/// Reads the [`MediaInfo`] of the `input` from the `output` of the ffprobe [`command()`].
+fn parse_output(input: &OsStr, output: Output) -> Result<MediaInfo, FfprobeError> {
+    if !output.status.success() {
+        return Err(FfprobeError::Failed {
+            input: input.to_string_lossy().to_string(),
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    let probe: ProbeOutput = serde_json::from_slice(&output.stdout)?;
+    let video = probe
+        .streams
+        .iter()
+        .find(|stream| stream.codec_type == "video")
+        .ok_or_else(|| FfprobeError::NoVideoStream {
+            input: input.to_string_lossy().to_string(),
+        })?;
+
+    // webm files usually only have a duration in the format
+    let duration = video
+        .duration
+        .as_deref()
+        .or(probe.format.duration.as_deref())
+        .and_then(|duration| duration.parse().ok())
+        .unwrap_or_default();
+
+    Ok(MediaInfo {
+        duration,
+        width: video.width.unwrap_or_default(),
+        height: video.height.unwrap_or_default(),
+        fps: parse_frame_rate(&video.avg_frame_rate)
+            .or_else(|| parse_frame_rate(&video.r_frame_rate))
+            .unwrap_or_default(),
+        codec: video.codec_name.clone().unwrap_or_default(),
+        pixel_format: video.pix_fmt.clone(),
+        has_audio: probe
+            .streams
+            .iter()
+            .any(|stream| stream.codec_type == "audio"),
+    })
+}
+
+/// Check whether or not the `ffprobe` command can be run
+pub fn is_available() -> bool {
+    Command::new("ffprobe").arg("-version").output().is_ok()
+}
+
+/// Parses a frame rate in the form of `30000/1001` or `30`.
+fn parse_frame_rate(rate: &Option<String>) -> Option<f64> {
+    let rate = rate.as_deref()?;
+    let fps = match rate.split_once('/') {
+        Some((num, den)) => num.parse::<f64>().ok()? / den.parse::<f64>().ok()?,
+        None => rate.parse().ok()?,
+    };
+    fps.is_finite().then_some(fps).filter(|fps| *fps > 0.0)
+}
+
+#[derive(Error, Debug)]
+pub enum FfprobeError {
+    #[error("ffprobe can not be found in path")]
+    NotFound,
+    #[error("Unknown Io error")]
+    Io { source: io::Error },
+    #[error("ffprobe could not read \"{input}\" (exit code {})\n{stderr}", exit_code.map_or("None".to_string(), |c| c.to_string()))]
+    Failed {
+        input: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+    #[error("the ffprobe output is not valid json")]
+    Json {
+        #[from]
+        source: serde_json::Error,
+    },
+    #[error("\"{input}\" has no video stream")]
+    NoVideoStream { input: String },
+}
+
+impl From<io::Error> for FfprobeError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => FfprobeError::NotFound,
+            _ => FfprobeError::Io { source: err },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ProbeOutput {
+    streams: Vec<Stream>,
+    format: Format,
+}
+
+#[derive(Deserialize, Debug)]
+struct Stream {
+    codec_type: String,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    pix_fmt: Option<String>,
+    r_frame_rate: Option<String>,
+    avg_frame_rate: Option<String>,
+    duration: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Format {
+    duration: Option<String>,
+}
@@ -0,0 +1,793 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use chrono::Datelike;
+use ordinal::Ordinal;
+
+use crate::{
+    cache, ffmpeg, ffprobe, gifsicle,
+    progress::{Progress, Stage},
+    report::{self, SegmentReport, StageTiming},
+    storyboard::{Storyboard, StoryboardSegment},
+    tenor,
+    upload::{self, Upload, UploadOptions, Uploader},
+    TimeForError,
+};
+
+#[cfg(feature = "async")]
+mod asynchronous;
+
+/// What to generate with a [`Generator`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use time_for::{ffmpeg::OutputFormat, GenerationRequest, Generator};
+///
+/// let request = GenerationRequest::query("coffee").format(OutputFormat::Gif);
+/// let result = Generator::new().generate(&request).unwrap();
+/// println!("{}", result.path.display());
+/// ```
+#[derive(Debug, Clone)]
+pub struct GenerationRequest {
+    storyboard: Storyboard,
+    format: ffmpeg::OutputFormat,
+    gif: ffmpeg::GifOptions,
+    normalization: ffmpeg::Normalization,
+    transition: ffmpeg::Transition,
+    considered_gifs: u8,
+    delay: i32,
+    max_size: Option<u64>,
+    uploaders: Vec<Uploader>,
+    upload_options: UploadOptions,
+    output: Option<PathBuf>,
+    cache_dir: Option<PathBuf>,
+    keep_intermediates: bool,
+}
+
+impl GenerationRequest {
+    /// Creates a request for the clips of the `storyboard`, as a WebM which isn't uploaded.
+    pub fn new(storyboard: Storyboard) -> Self {
+        Self {
+            storyboard,
+            format: ffmpeg::OutputFormat::default(),
+            gif: ffmpeg::GifOptions::default(),
+            normalization: ffmpeg::Normalization::default(),
+            transition: ffmpeg::Transition::default(),
+            considered_gifs: 5,
+            delay: 0,
+            max_size: None,
+            uploaders: vec![],
+            upload_options: UploadOptions::default(),
+            output: None,
+            cache_dir: None,
+            keep_intermediates: false,
+        }
+    }
+
+    /// Creates a request for a "look at time" clip with the current time followed by a clip of
+    /// the `query`, like the CLI does.
+    pub fn query(query: &str) -> Self {
+        Self::new(Storyboard::time_for(Some(query), None))
+    }
+
+    /// The format of the final file.
+    pub fn format(mut self, format: ffmpeg::OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The palette and compression options, only used for GIFs.
+    pub fn gif(mut self, gif: ffmpeg::GifOptions) -> Self {
+        self.gif = gif;
+        self
+    }
+
+    /// The size and frame rate all clips are scaled to.
+    pub fn normalization(mut self, normalization: ffmpeg::Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// The transition between the segments which don't have their own, unless the storyboard
+    /// sets one.
+    pub fn transition(mut self, transition: ffmpeg::Transition) -> Self {
+        self.transition = transition;
+        self
+    }
+
+    /// The number of GIFs a random one is picked from for segments which don't set it.
+    pub fn considered_gifs(mut self, considered_gifs: u8) -> Self {
+        self.considered_gifs = considered_gifs;
+        self
+    }
+
+    /// The offset in seconds added to the time on the GIF, to compensate for the generation
+    /// time.
+    pub fn delay(mut self, delay: i32) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// The size limit of the final file in bytes, it is re-encoded until it fits.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Uploads the final file to the first of the `uploaders` that accepts it. Nothing is
+    /// uploaded without uploaders.
+    pub fn upload(mut self, uploaders: Vec<Uploader>, options: UploadOptions) -> Self {
+        self.uploaders = uploaders;
+        self.upload_options = options;
+        self
+    }
+
+    /// Copies the final file to the `output` path, instead of keeping it in the working
+    /// directory.
+    pub fn output(mut self, output: PathBuf) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// The directory the working directory of the run is created in, `time-for` in the temp
+    /// directory by default.
+    pub fn cache_dir(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dir = Some(cache_dir);
+        self
+    }
+
+    /// Keeps the downloaded clips and other intermediate files in the working directory.
+    pub fn keep_intermediates(mut self, keep_intermediates: bool) -> Self {
+        self.keep_intermediates = keep_intermediates;
+        self
+    }
+}
+
+/// The outcome of [`Generator::generate()`].
+#[derive(Debug, Clone)]
+pub struct GenerationResult {
+    /// The final file
+    pub path: PathBuf,
+    /// The working directory of the run, which only contains the final file unless the
+    /// intermediates were kept. It is removed if the final file was copied to an output.
+    pub work_dir: PathBuf,
+    pub format: ffmpeg::OutputFormat,
+    /// The size of the final file in bytes
+    pub size: u64,
+    /// The duration of the final file in seconds
+    pub duration: f64,
+    /// The text drawn on the last clip
+    pub caption: String,
+    pub segments: Vec<SegmentReport>,
+    /// The upload of the final file, `None` if it wasn't uploaded or all uploaders failed
+    pub upload: Option<Upload>,
+    /// How long every stage took, in the order they ran
+    pub stages: Vec<StageTiming>,
+    /// The problems which didn't stop the generation, e.g. a failed upload
+    pub warnings: Vec<String>,
+}
+
+/// Generates the GIFs of [`GenerationRequest`]s, reporting the [`Progress`] to a callback.
+pub struct Generator {
+    on_progress: Box<dyn FnMut(&Progress) + Send>,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Self {
+            on_progress: Box::new(|_| {}),
+        }
+    }
+}
+
+impl Generator {
+    /// Creates a generator which doesn't report its progress.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `on_progress` for every step of the generation and every warning.
+    pub fn on_progress(mut self, on_progress: impl FnMut(&Progress) + Send + 'static) -> Self {
+        self.on_progress = Box::new(on_progress);
+        self
+    }
+
+    /// Downloads the clips of the `request`, renders them into the final file and uploads it.
+    ///
+    /// A failed upload is only a warning, so the local file is still returned.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if ffmpeg or ffprobe are not installed, the
+    /// storyboard or transition are invalid, a clip could not be downloaded or is invalid, or
+    /// the file could not be rendered or fit the size limit.
+    pub fn generate(
+        &mut self,
+        request: &GenerationRequest,
+    ) -> Result<GenerationResult, TimeForError> {
+        let mut run = Run::start(request)?;
+
+        //* Download a random gif for every query and make sure all clips are actual videos
+        let started = Instant::now();
+        let mut clips = vec![];
+        for (i, segment) in request.storyboard.segments.iter().enumerate() {
+            clips.push(self.fetch_clip(request, &run.work_dir, i, segment)?);
+        }
+        report::add_timing(&mut run.stages, Stage::Download, started.elapsed());
+        let segments = run.add_segments(request, clips)?;
+
+        //* Normalize, add text and stitch the gifs in one go
+        let started = Instant::now();
+        let duration = ffmpeg::rendered_duration(&segments);
+        self.start_encode(duration, String::new());
+        ffmpeg::render(
+            &segments,
+            &request.normalization,
+            request.format,
+            &request.gif,
+            &run.path,
+            |event| self.emit(encode_progress(event)),
+        )?;
+        self.finish_stage(Stage::Encode);
+        if request.format == ffmpeg::OutputFormat::Gif {
+            self.compress_gif(&run.path, &request.gif, &mut run.warnings)?;
+        }
+
+        //* Make sure the file fits the size limit of where it is sent to
+        if let Some(limit) = request.max_size {
+            self.fit_size(&run.path, request, limit, duration, &mut run.warnings)?;
+        }
+        report::add_timing(&mut run.stages, Stage::Encode, started.elapsed());
+
+        //* Copy the file to where it is wanted, the copy in the working directory is cleaned up
+        run.copy_to_output(request.output.as_deref())?;
+        run.duration = final_duration(ffprobe::probe(&run.path), duration);
+
+        //* Upload file
+        if let Some(started) = self.start_upload(request) {
+            let res = upload::upload(
+                &run.path,
+                request.format,
+                &request.uploaders,
+                &request.upload_options,
+            );
+            self.finish_upload(&mut run, started, res);
+        }
+
+        //* Only keep the final file, unless the intermediates are wanted for debugging
+        let cleaned = run.remove_intermediates(request.keep_intermediates);
+        Ok(self.finish_run(run, request.format, cleaned))
+    }
+
+    fn emit(&mut self, progress: Progress) {
+        (self.on_progress)(&progress);
+    }
+
+    /// Reports the `warning` and adds it to the `warnings` of the result.
+    fn warn(&mut self, warnings: &mut Vec<String>, warning: String) {
+        self.emit(Progress::Warning(warning.clone()));
+        warnings.push(warning);
+    }
+
+    /// Reports the start of an encoding of a file with the `duration` in seconds.
+    fn start_encode(&mut self, duration: f64, message: String) {
+        self.emit(Progress::Started {
+            stage: Stage::Encode,
+            total: Some((duration * 1000.0) as u64),
+            message,
+        });
+    }
+
+    fn finish_stage(&mut self, stage: Stage) {
+        self.emit(Progress::Finished { stage });
+    }
+
+    /// Downloads a random GIF for the `segment` with the index `i` into the `work_dir`, or uses
+    /// its file, and inspects it.
+    fn fetch_clip(
+        &mut self,
+        request: &GenerationRequest,
+        work_dir: &Path,
+        i: usize,
+        segment: &StoryboardSegment,
+    ) -> Result<Clip, TimeForError> {
+        let (file, gif) = match (&segment.query, &segment.file) {
+            (Some(query), _) => {
+                let file = gif_file(work_dir, i);
+                let gif = tenor::random_webm(
+                    query,
+                    Some(segment.considered_gifs.unwrap_or(request.considered_gifs)),
+                )?;
+                self.download_file(&gif.url, &file)?;
+                (file, Some(gif))
+            }
+            (None, Some(file)) => (file.clone(), None),
+            (None, None) => unreachable!("the storyboard is validated"),
+        };
+        let info = check_download(&file, ffprobe::probe(&file)?)?;
+        Ok(Clip { file, info, gif })
+    }
+
+    fn download_file(&mut self, url: &str, file_path: &Path) -> Result<(), TimeForError> {
+        let mut res = reqwest::blocking::get(url)?.error_for_status()?;
+        self.emit(Progress::Started {
+            stage: Stage::Download,
+            total: res.content_length(),
+            message: file_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        });
+
+        let random_webm_file = fs::File::create(file_path)?;
+        io::copy(
+            &mut res,
+            &mut ProgressWriter {
+                inner: random_webm_file,
+                written: 0,
+                on_write: |written| {
+                    self.emit(Progress::Advanced {
+                        stage: Stage::Download,
+                        position: written,
+                        message: None,
+                    })
+                },
+            },
+        )?;
+        self.finish_stage(Stage::Download);
+        Ok(())
+    }
+
+    /// Compresses the GIF `file` lossily with gifsicle if the `gif` options ask for it.
+    ///
+    /// The compression is skipped with a warning if gifsicle is not installed.
+    fn compress_gif(
+        &mut self,
+        file: &Path,
+        gif: &ffmpeg::GifOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), TimeForError> {
+        let Some(lossy) = self.start_compression(gif) else {
+            return Ok(());
+        };
+        let res = gifsicle::optimize(file, lossy);
+        self.finish_compression(res, warnings)
+    }
+
+    /// Reports the start of the compression and returns its level, `None` if the `gif`
+    /// options don't compress.
+    fn start_compression(&mut self, gif: &ffmpeg::GifOptions) -> Option<u32> {
+        let lossy = gif.lossy?;
+        self.emit(Progress::Started {
+            stage: Stage::Encode,
+            total: None,
+            message: "compressing with gifsicle".to_string(),
+        });
+        Some(lossy)
+    }
+
+    /// Reports the end of the compression, a missing gifsicle is only a warning.
+    fn finish_compression(
+        &mut self,
+        res: Result<(), gifsicle::GifsicleError>,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), TimeForError> {
+        self.finish_stage(Stage::Encode);
+        match res {
+            Err(gifsicle::GifsicleError::NotFound) => {
+                let warning =
+                    "gifsicle can not be found in path, the GIF is not compressed lossily";
+                // The warning is only given once, even when compressing again to fit the size
+                // limit
+                if !warnings.iter().any(|w| w == warning) {
+                    self.warn(warnings, warning.to_string());
+                }
+                Ok(())
+            }
+            res => Ok(res?),
+        }
+    }
+
+    /// Re-encodes the `file` with the [`ffmpeg::SHRINK_STEPS`] until it is at most `limit`
+    /// bytes.
+    ///
+    /// The `duration` of the file in seconds is used to report the progress.
+    fn fit_size(
+        &mut self,
+        file: &Path,
+        request: &GenerationRequest,
+        limit: u64,
+        duration: f64,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), TimeForError> {
+        let size = file_size(file)?;
+        if size <= limit {
+            return Ok(());
+        }
+
+        let shrunk_file = add_to_file_name(file, "_shrunk");
+        let fitted = self.shrink_until_fits(file, &shrunk_file, request, limit, duration, warnings);
+        keep_shrunk(fitted, file, &shrunk_file, size, limit)
+    }
+
+    /// Re-encodes the `file` into the `shrunk_file` with every step of the
+    /// [`ffmpeg::SHRINK_STEPS`] until it is at most `limit` bytes, returns whether it fit.
+    fn shrink_until_fits(
+        &mut self,
+        file: &Path,
+        shrunk_file: &Path,
+        request: &GenerationRequest,
+        limit: u64,
+        duration: f64,
+        warnings: &mut Vec<String>,
+    ) -> Result<bool, TimeForError> {
+        for (i, step) in ffmpeg::SHRINK_STEPS.iter().enumerate() {
+            self.start_encode(duration, format!("fitting size, try {}", i + 1));
+            ffmpeg::shrink(
+                file,
+                step,
+                request.format,
+                &request.gif,
+                shrunk_file,
+                |event| self.emit(encode_progress(event)),
+            )?;
+            self.finish_stage(Stage::Encode);
+            if request.format == ffmpeg::OutputFormat::Gif {
+                self.compress_gif(shrunk_file, &request.gif, warnings)?;
+            }
+
+            if file_size(shrunk_file)? <= limit {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Reports the start of the upload, `None` if the `request` has no uploaders.
+    fn start_upload(&mut self, request: &GenerationRequest) -> Option<Instant> {
+        let uploader = request.uploaders.first()?;
+        self.emit(Progress::Started {
+            stage: Stage::Upload,
+            total: None,
+            message: uploader.to_string(),
+        });
+        Some(Instant::now())
+    }
+
+    /// Reports the end of the upload which `started` at the given time and keeps the upload
+    /// for the result, a failed upload is only a warning.
+    fn finish_upload(
+        &mut self,
+        run: &mut Run,
+        started: Instant,
+        res: Result<Upload, upload::UploadError>,
+    ) {
+        self.finish_stage(Stage::Upload);
+        report::add_timing(&mut run.stages, Stage::Upload, started.elapsed());
+        match res {
+            Ok(upload) => run.upload = Some(upload),
+            Err(e) => self.warn(&mut run.warnings, e.to_string()),
+        }
+    }
+
+    /// Turns the `run` into the result, warning if the intermediate files weren't `cleaned`.
+    fn finish_run(
+        &mut self,
+        mut run: Run,
+        format: ffmpeg::OutputFormat,
+        cleaned: Result<(), cache::CacheError>,
+    ) -> GenerationResult {
+        if let Err(e) = cleaned {
+            self.warn(
+                &mut run.warnings,
+                format!("The intermediate files could not be removed: {}", e),
+            );
+        }
+        GenerationResult {
+            path: run.path,
+            work_dir: run.work_dir,
+            format,
+            size: run.size,
+            duration: run.duration,
+            caption: run.caption,
+            segments: run.segments,
+            upload: run.upload,
+            stages: run.stages,
+            warnings: run.warnings,
+        }
+    }
+}
+
+/// The state of a generation, shared by [`Generator::generate()`] and its async version.
+struct Run {
+    work_dir: PathBuf,
+    /// The final file, in the working directory until it is copied to the output
+    path: PathBuf,
+    caption: String,
+    segments: Vec<SegmentReport>,
+    size: u64,
+    duration: f64,
+    upload: Option<Upload>,
+    stages: Vec<StageTiming>,
+    warnings: Vec<String>,
+}
+
+impl Run {
+    /// Makes sure ffmpeg and ffprobe are installed and the storyboard and transition of the
+    /// `request` are valid, and creates the working directory of the run.
+    fn start(request: &GenerationRequest) -> Result<Run, TimeForError> {
+        if !ffmpeg::is_available() {
+            return Err(TimeForError::FfmpegNotFound);
+        }
+        if !ffprobe::is_available() {
+            return Err(ffprobe::FfprobeError::NotFound.into());
+        }
+        request.storyboard.validate()?;
+        let duration = request.transition.duration;
+        if !(duration > 0.0 && duration.is_finite()) {
+            return Err(TimeForError::InvalidTransitionDuration { duration });
+        }
+
+        // Every run gets its own directory, so concurrent runs don't overwrite each other's files
+        let cache_dir = match &request.cache_dir {
+            Some(cache_dir) => cache_dir.clone(),
+            None => cache::base_dir(false)?,
+        };
+        let work_dir = cache::create_run_dir(&cache_dir)?;
+        let path = work_dir
+            .join("full")
+            .with_extension(request.format.extension());
+        Ok(Run {
+            work_dir,
+            path,
+            caption: String::new(),
+            segments: vec![],
+            size: 0,
+            duration: 0.0,
+            upload: None,
+            stages: vec![],
+            warnings: vec![],
+        })
+    }
+
+    /// Builds the segments to render from the downloaded or local `clips`, keeping their
+    /// reports and the caption for the result.
+    fn add_segments(
+        &mut self,
+        request: &GenerationRequest,
+        clips: Vec<Clip>,
+    ) -> Result<Vec<ffmpeg::Segment>, TimeForError> {
+        let (segments, reports) = build_segments(request, clips)?;
+        self.caption = caption(&segments);
+        self.segments = reports;
+        Ok(segments)
+    }
+
+    /// Copies the final file to the `output`, if there is one, and reads its size.
+    fn copy_to_output(&mut self, output: Option<&Path>) -> Result<(), TimeForError> {
+        if let Some(output) = output {
+            if let Some(parent) = output
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                fs::create_dir_all(parent).map_err(|e| TimeForError::Io { source: e })?;
+            }
+            fs::copy(&self.path, output).map_err(|e| TimeForError::Io { source: e })?;
+            self.path = output.to_path_buf();
+        }
+        self.size = file_size(&self.path)?;
+        Ok(())
+    }
+
+    /// Removes everything but the final file from the working directory, unless the
+    /// intermediates are kept.
+    fn remove_intermediates(&self, keep_intermediates: bool) -> Result<(), cache::CacheError> {
+        if keep_intermediates {
+            return Ok(());
+        }
+        cache::remove_intermediates(&self.work_dir, &self.path)
+    }
+}
+
+/// A downloaded or local clip of a segment.
+struct Clip {
+    file: PathBuf,
+    info: ffprobe::MediaInfo,
+    /// The GIF the clip was downloaded from, `None` for local files
+    gif: Option<tenor::TenorGif>,
+}
+
+/// The file the GIF of the segment with the index `i` is downloaded to.
+fn gif_file(work_dir: &Path, i: usize) -> PathBuf {
+    work_dir.join(format!("segment_{}.webm", i + 1))
+}
+
+/// The size of the `file` in bytes.
+fn file_size(file: &Path) -> Result<u64, TimeForError> {
+    Ok(fs::metadata(file)
+        .map_err(|e| TimeForError::Io { source: e })?
+        .len())
+}
+
+/// Replaces the `file` with the `shrunk_file` if it `fitted` the `limit`, otherwise removes
+/// the `shrunk_file` and fails with the original `size`.
+fn keep_shrunk(
+    fitted: Result<bool, TimeForError>,
+    file: &Path,
+    shrunk_file: &Path,
+    size: u64,
+    limit: u64,
+) -> Result<(), TimeForError> {
+    match fitted {
+        Ok(true) => fs::rename(shrunk_file, file).map_err(|e| TimeForError::Io { source: e }),
+        res => {
+            // The last attempt is of no use and would only be left behind next to the file
+            let _ = fs::remove_file(shrunk_file);
+            res?;
+            Err(TimeForError::TooLarge { size, limit })
+        }
+    }
+}
+
+/// Builds the segments to render from the storyboard of the `request` and the downloaded or
+/// local `clips` of its segments, together with the reports of the segments.
+fn build_segments(
+    request: &GenerationRequest,
+    clips: Vec<Clip>,
+) -> Result<(Vec<ffmpeg::Segment>, Vec<SegmentReport>), TimeForError> {
+    let (files, gifs): (Vec<_>, Vec<_>) = clips
+        .into_iter()
+        .map(|clip| ((clip.file, clip.info), clip.gif))
+        .unzip();
+
+    //* Create text for gif
+    let time = chrono::Local::now() + chrono::Duration::seconds(request.delay as i64);
+    let day_ord = Ordinal(time.day()).to_string();
+    let format_str = format!("It is %H:%M:%S %A %B {day_ord} %Y");
+    let time_text = time.format(&format_str).to_string();
+
+    let storyboard = &request.storyboard;
+    let default_transition = ffmpeg::Transition {
+        kind: storyboard.transition.unwrap_or(request.transition.kind),
+        duration: storyboard
+            .transition_duration
+            .unwrap_or(request.transition.duration),
+    };
+    let segments: Vec<ffmpeg::Segment> = storyboard
+        .segments
+        .iter()
+        .zip(files)
+        .map(|(segment, (file, info))| {
+            let start = segment.start.unwrap_or_default();
+            if start < 0.0 || start >= info.duration {
+                return Err(TimeForError::StartOutOfRange {
+                    file,
+                    start,
+                    duration: info.duration,
+                });
+            }
+            let trim =
+                (segment.start.is_some() || segment.duration.is_some()).then_some(ffmpeg::Trim {
+                    start,
+                    duration: segment.duration,
+                });
+            let rest = info.duration - start;
+            Ok(ffmpeg::Segment {
+                file,
+                text: segment.caption(&time_text),
+                trim,
+                duration: segment.duration.map_or(rest, |duration| duration.min(rest)),
+                effects: ffmpeg::Effects {
+                    speed: segment.speed.unwrap_or(1.0),
+                    reverse: segment.reverse.unwrap_or_default(),
+                    boomerang: segment.boomerang.unwrap_or_default(),
+                    min_duration: segment.min_duration,
+                },
+                transition: ffmpeg::Transition {
+                    kind: segment.transition.unwrap_or(default_transition.kind),
+                    duration: segment
+                        .transition_duration
+                        .unwrap_or(default_transition.duration),
+                },
+            })
+        })
+        .collect::<Result<_, TimeForError>>()?;
+    let segment_reports = storyboard
+        .segments
+        .iter()
+        .zip(&segments)
+        .zip(gifs)
+        .map(|((segment, rendered), gif)| SegmentReport {
+            query: segment.query.clone(),
+            source: gif
+                .as_ref()
+                .map_or(rendered.file.to_string_lossy().to_string(), |gif| {
+                    gif.url.clone()
+                }),
+            gif_id: gif.map(|gif| gif.id),
+            caption: rendered.text.clone(),
+            duration: rendered.output_duration(),
+        })
+        .collect();
+    Ok((segments, segment_reports))
+}
+
+/// The text drawn on the last clip.
+fn caption(segments: &[ffmpeg::Segment]) -> String {
+    segments
+        .last()
+        .map(|segment| segment.text.clone())
+        .unwrap_or_default()
+}
+
+/// The [`Progress`] of an ffmpeg encoding, with the position in milliseconds of output and
+/// the encoding speed.
+fn encode_progress(event: &ffmpeg::ProgressEvent) -> Progress {
+    Progress::Advanced {
+        stage: Stage::Encode,
+        position: event.out_time.as_millis() as u64,
+        message: event
+            .speed
+            .map(|speed| format!("frame {} ({:.1}x)", event.frame, speed)),
+    }
+}
+
+/// The duration of the final file from its `info`, which changes when [`Generator::fit_size()`]
+/// lowers the frame rate, or the `rendered` duration if ffprobe can't read it, e.g. for
+/// animated WebP files.
+fn final_duration(info: Result<ffprobe::MediaInfo, ffprobe::FfprobeError>, rendered: f64) -> f64 {
+    info.ok()
+        .map(|info| info.duration)
+        .filter(|duration| *duration > 0.0)
+        .unwrap_or(rendered)
+}
+
+/// Makes sure the `info` of the downloaded `file` is a video with a size and duration.
+fn check_download(
+    file: &Path,
+    info: ffprobe::MediaInfo,
+) -> Result<ffprobe::MediaInfo, TimeForError> {
+    if info.duration <= 0.0 || info.width == 0 || info.height == 0 {
+        return Err(TimeForError::InvalidDownload {
+            file: file.to_path_buf(),
+            info,
+        });
+    }
+    Ok(info)
+}
+
+/// Adds the `addition` string to the file name of the `file`, keeping its extension
+fn add_to_file_name(file: &Path, addition: &str) -> PathBuf {
+    let file_name = file.file_stem().unwrap_or_default().to_string_lossy() + addition;
+    let path = file.with_file_name(file_name.as_ref());
+    match file.extension() {
+        Some(extension) => path.with_extension(extension),
+        None => path,
+    }
+}
+
+/// Counts the bytes written to the `inner` writer and reports the total to `on_write`.
+struct ProgressWriter<W, F> {
+    inner: W,
+    written: u64,
+    on_write: F,
+}
+
+impl<W: Write, F: FnMut(u64)> Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        (self.on_write)(self.written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,266 @@
+use std::{path::Path, time::Instant};
+
+use futures_util::future::try_join_all;
+use tokio::io::AsyncWriteExt;
+
+use super::{
+    add_to_file_name, check_download, encode_progress, final_duration, gif_file, keep_shrunk, Clip,
+    GenerationRequest, GenerationResult, Generator, Run,
+};
+use crate::{
+    ffmpeg, ffprobe, gifsicle,
+    progress::{Progress, Stage},
+    report,
+    storyboard::StoryboardSegment,
+    tenor, upload, TimeForError,
+};
+
+impl Generator {
+    /// Generates the file of the `request` like [`generate()`](Generator::generate) without
+    /// blocking the async runtime.
+    ///
+    /// The GIFs of all segments are searched, downloaded and inspected at the same time, so
+    /// the download is only reported as a whole. ffmpeg, ffprobe and gifsicle are killed if
+    /// the returned future is dropped before it finished.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if ffmpeg or ffprobe are not installed, the
+    /// storyboard or transition are invalid, a clip could not be downloaded or is invalid, or
+    /// the file could not be rendered or fit the size limit.
+    pub async fn generate_async(
+        &mut self,
+        request: &GenerationRequest,
+    ) -> Result<GenerationResult, TimeForError> {
+        let mut run = blocking({
+            let request = request.clone();
+            move || Run::start(&request)
+        })
+        .await?;
+
+        //* Download a random gif for every query and make sure all clips are actual videos
+        let started = Instant::now();
+        self.emit(Progress::Started {
+            stage: Stage::Download,
+            total: None,
+            message: format!("{} clips", request.storyboard.segments.len()),
+        });
+        let client = reqwest::Client::new();
+        let clips = try_join_all(
+            request
+                .storyboard
+                .segments
+                .iter()
+                .enumerate()
+                .map(|(i, segment)| fetch_clip(&client, request, &run.work_dir, i, segment)),
+        )
+        .await?;
+        self.finish_stage(Stage::Download);
+        report::add_timing(&mut run.stages, Stage::Download, started.elapsed());
+        let segments = run.add_segments(request, clips)?;
+
+        //* Normalize, add text and stitch the gifs in one go
+        let started = Instant::now();
+        let duration = ffmpeg::rendered_duration(&segments);
+        self.start_encode(duration, String::new());
+        ffmpeg::render_async(
+            &segments,
+            &request.normalization,
+            request.format,
+            &request.gif,
+            &run.path,
+            |event| self.emit(encode_progress(event)),
+        )
+        .await?;
+        self.finish_stage(Stage::Encode);
+        if request.format == ffmpeg::OutputFormat::Gif {
+            self.compress_gif_async(&run.path, &request.gif, &mut run.warnings)
+                .await?;
+        }
+
+        //* Make sure the file fits the size limit of where it is sent to
+        if let Some(limit) = request.max_size {
+            self.fit_size_async(&run.path, request, limit, duration, &mut run.warnings)
+                .await?;
+        }
+        report::add_timing(&mut run.stages, Stage::Encode, started.elapsed());
+
+        //* Copy the file to where it is wanted, the copy in the working directory is cleaned up
+        let output = request.output.clone();
+        run = blocking(move || {
+            run.copy_to_output(output.as_deref())?;
+            Ok::<_, TimeForError>(run)
+        })
+        .await?;
+        run.duration = final_duration(ffprobe::probe_async(&run.path).await, duration);
+
+        //* Upload file
+        if let Some(started) = self.start_upload(request) {
+            let res = upload::upload_async(
+                &run.path,
+                request.format,
+                &request.uploaders,
+                &request.upload_options,
+            )
+            .await;
+            self.finish_upload(&mut run, started, res);
+        }
+
+        //* Only keep the final file, unless the intermediates are wanted for debugging
+        let keep_intermediates = request.keep_intermediates;
+        let (run, cleaned) = blocking(move || {
+            let cleaned = run.remove_intermediates(keep_intermediates);
+            (run, cleaned)
+        })
+        .await;
+        Ok(self.finish_run(run, request.format, cleaned))
+    }
+
+    /// Compresses the GIF `file` like [`compress_gif()`](Generator::compress_gif) without
+    /// blocking the async runtime.
+    async fn compress_gif_async(
+        &mut self,
+        file: &Path,
+        gif: &ffmpeg::GifOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), TimeForError> {
+        let Some(lossy) = self.start_compression(gif) else {
+            return Ok(());
+        };
+        let res = gifsicle::optimize_async(file, lossy).await;
+        self.finish_compression(res, warnings)
+    }
+
+    /// Re-encodes the `file` like [`fit_size()`](Generator::fit_size) without blocking the
+    /// async runtime.
+    async fn fit_size_async(
+        &mut self,
+        file: &Path,
+        request: &GenerationRequest,
+        limit: u64,
+        duration: f64,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), TimeForError> {
+        let size = file_size_async(file).await?;
+        if size <= limit {
+            return Ok(());
+        }
+
+        let shrunk_file = add_to_file_name(file, "_shrunk");
+        let fitted = self
+            .shrink_until_fits_async(file, &shrunk_file, request, limit, duration, warnings)
+            .await;
+        let file = file.to_path_buf();
+        blocking(move || keep_shrunk(fitted, &file, &shrunk_file, size, limit)).await
+    }
+
+    /// Re-encodes the `file` into the `shrunk_file` like
+    /// [`shrink_until_fits()`](Generator::shrink_until_fits) without blocking the async runtime.
+    async fn shrink_until_fits_async(
+        &mut self,
+        file: &Path,
+        shrunk_file: &Path,
+        request: &GenerationRequest,
+        limit: u64,
+        duration: f64,
+        warnings: &mut Vec<String>,
+    ) -> Result<bool, TimeForError> {
+        for (i, step) in ffmpeg::SHRINK_STEPS.iter().enumerate() {
+            self.start_encode(duration, format!("fitting size, try {}", i + 1));
+            ffmpeg::shrink_async(
+                file,
+                step,
+                request.format,
+                &request.gif,
+                shrunk_file,
+                |event| self.emit(encode_progress(event)),
+            )
+            .await?;
+            self.finish_stage(Stage::Encode);
+            if request.format == ffmpeg::OutputFormat::Gif {
+                self.compress_gif_async(shrunk_file, &request.gif, warnings)
+                    .await?;
+            }
+
+            if file_size_async(shrunk_file).await? <= limit {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Downloads a random GIF for the `segment` with the index `i` into the `work_dir`, or uses
+/// its file, and inspects it.
+async fn fetch_clip(
+    client: &reqwest::Client,
+    request: &GenerationRequest,
+    work_dir: &Path,
+    i: usize,
+    segment: &StoryboardSegment,
+) -> Result<Clip, TimeForError> {
+    let (file, gif) = match (&segment.query, &segment.file) {
+        (Some(query), _) => {
+            let file = gif_file(work_dir, i);
+            let gif = tenor::random_webm_async(
+                query,
+                Some(segment.considered_gifs.unwrap_or(request.considered_gifs)),
+            )
+            .await?;
+            download_file(client, &gif.url, &file).await?;
+            (file, Some(gif))
+        }
+        (None, Some(file)) => (file.clone(), None),
+        (None, None) => unreachable!("the storyboard is validated"),
+    };
+    let info = check_download(&file, ffprobe::probe_async(&file).await?)?;
+    Ok(Clip { file, info, gif })
+}
+
+async fn download_file(
+    client: &reqwest::Client,
+    url: &str,
+    file_path: &Path,
+) -> Result<(), TimeForError> {
+    let mut res = client.get(url).send().await?.error_for_status()?;
+    let mut file = tokio::fs::File::create(file_path).await?;
+    while let Some(chunk) = res.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+/// The size of the `file` in bytes, like [`file_size()`](super::file_size) without blocking
+/// the async runtime.
+async fn file_size_async(file: &Path) -> Result<u64, TimeForError> {
+    Ok(tokio::fs::metadata(file)
+        .await
+        .map_err(|e| TimeForError::Io { source: e })?
+        .len())
+}
+
+/// Runs the blocking file access `f` on the blocking threads of tokio, so it doesn't stall
+/// the other tasks of the runtime.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        // The task is never cancelled, so it can only have panicked
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send<T: Send>(_: T) {}
+
+    #[test]
+    fn generate_async_is_send() {
+        // The future is never polled, it only has to be spawnable on a multi-threaded runtime
+        let mut generator = Generator::new();
+        let request = GenerationRequest::query("coffee");
+        assert_send(generator.generate_async(&request));
+    }
+}
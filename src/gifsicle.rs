@@ -0,0 +1,80 @@
+use std::{
+    io,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+use thiserror::Error;
+
+/// Optimizes the GIF `file` in place with gifsicle's lossy compression at the `lossy` level.
+///
+/// Higher levels give smaller files with more noise, 30-200 is a sensible range.
+///
+/// # Errors
+///
+/// This function will return an error if gifsicle could not be run or failed.
+pub fn optimize(file: &Path, lossy: u32) -> Result<(), GifsicleError> {
+    check_output(command(file, lossy).output()?)
+}
+
+/// Optimizes the GIF `file` like [`optimize()`] without blocking the async runtime.
+///
+/// # Errors
+///
+/// This function will return an error if gifsicle could not be run or failed.
+#[cfg(feature = "async")]
+pub async fn optimize_async(file: &Path, lossy: u32) -> Result<(), GifsicleError> {
+    let output = tokio::process::Command::from(command(file, lossy))
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    check_output(output)
+}
+
+/// The gifsicle command optimizing the `file` in place at the `lossy` level.
+fn command(file: &Path, lossy: u32) -> Command {
+    let mut command = Command::new("gifsicle");
+    command
+        .args(["--batch", "-O3"])
+        .arg(format!("--lossy={}", lossy))
+        .arg(file)
+        .stdin(Stdio::null());
+    command
+}
+
+fn check_output(output: Output) -> Result<(), GifsicleError> {
+    if !output.status.success() {
+        return Err(GifsicleError::Failed {
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Check whether or not the `gifsicle` command can be run
+pub fn is_available() -> bool {
+    Command::new("gifsicle").arg("--version").output().is_ok()
+}
+
+#[derive(Error, Debug)]
+pub enum GifsicleError {
+    #[error("gifsicle can not be found in path")]
+    NotFound,
+    #[error("Unknown Io error")]
+    Io { source: io::Error },
+    #[error("gifsicle failed (exit code {})\n{stderr}", exit_code.map_or("None".to_string(), |c| c.to_string()))]
+    Failed {
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+}
+
+impl From<io::Error> for GifsicleError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => GifsicleError::NotFound,
+            _ => GifsicleError::Io { source: err },
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+    process,
+};
+
+use thiserror::Error;
+
+use crate::upload::Upload;
+
+/// The file every upload is recorded in, so it can be deleted later on.
+///
+/// It lives in the data directory (e.g. `~/.local/share/time-for/uploads.json` or
+/// `%APPDATA%\time-for\uploads.json`) since the temp directory may be cleaned at any time.
+pub fn history_file() -> Result<PathBuf, HistoryError> {
+    Ok(dirs::data_dir()
+        .ok_or(HistoryError::NoDataDir)?
+        .join("time-for")
+        .join("uploads.json"))
+}
+
+/// Loads all recorded uploads, oldest first.
+///
+/// # Errors
+///
+/// This function will return an error if the history file exists but can not be read or parsed.
+pub fn load() -> Result<Vec<Upload>, HistoryError> {
+    match fs::read_to_string(history_file()?) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Appends the `upload` to the recorded uploads.
+///
+/// # Errors
+///
+/// This function will return an error if the history file can not be read or written.
+pub fn add(upload: &Upload) -> Result<(), HistoryError> {
+    let mut uploads = load()?;
+    uploads.push(upload.clone());
+    save(&uploads)
+}
+
+/// Removes the upload with the `link` from the recorded uploads.
+///
+/// # Errors
+///
+/// This function will return an error if the history file can not be read or written.
+pub fn remove(link: &str) -> Result<(), HistoryError> {
+    let mut uploads = load()?;
+    uploads.retain(|upload| upload.link != link);
+    save(&uploads)
+}
+
+/// Finds the most recent upload matching the `target`, which can either be the link or the id
+/// of the upload.
+///
+/// # Errors
+///
+/// This function will return an error if the history file can not be read.
+pub fn find(target: &str) -> Result<Option<Upload>, HistoryError> {
+    Ok(load()?
+        .into_iter()
+        .rev()
+        .find(|upload| upload.link == target || upload.id() == target))
+}
+
+/// The most recent upload, if there is one.
+///
+/// # Errors
+///
+/// This function will return an error if the history file can not be read.
+pub fn last() -> Result<Option<Upload>, HistoryError> {
+    Ok(load()?.pop())
+}
+
+/// Writes the `uploads` to a temporary file first and then replaces the history file with it,
+/// so an interrupted write can't leave a truncated history behind.
+fn save(uploads: &[Upload]) -> Result<(), HistoryError> {
+    let path = history_file()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension(format!("json.{}.tmp", process::id()));
+    fs::write(&temp, serde_json::to_string_pretty(uploads)?)?;
+    if let Err(e) = fs::rename(&temp, &path) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("no data directory found to store the upload history in")]
+    NoDataDir,
+    #[error("could not read or write the upload history")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+    #[error("the upload history is not valid json")]
+    Json {
+        #[from]
+        source: serde_json::Error,
+    },
+}
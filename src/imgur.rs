@@ -0,0 +1,335 @@
+use std::{
+    env, fs,
+    io::{self, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::secrets;
+
+/// The OAuth token of a logged in Imgur account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// When the access token expires as a unix timestamp
+    pub expires_at: i64,
+    pub account_username: String,
+}
+
+impl Token {
+    /// Whether the access token is expired or is about to expire within the next minute.
+    pub fn is_expired(&self) -> bool {
+        chrono::Utc::now().timestamp() + 60 >= self.expires_at
+    }
+}
+
+/// The file the Imgur token is stored in, e.g. `~/.config/time-for/imgur_token.json`
+/// or `%APPDATA%\time-for\imgur_token.json`.
+pub fn token_file() -> Result<PathBuf, ImgurError> {
+    Ok(dirs::config_dir()
+        .ok_or(ImgurError::NoConfigDir)?
+        .join("time-for")
+        .join("imgur_token.json"))
+}
+
+/// The url to authorize time-for to upload to an Imgur account.
+///
+/// After logging in, Imgur redirects to the callback url of the application with the token
+/// in the url, which then has to be passed to [`login()`].
+pub fn authorize_url() -> String {
+    format!(
+        "https://api.imgur.com/oauth2/authorize?client_id={}&response_type=token",
+        secrets::IMGUR_CLIENT_ID
+    )
+}
+
+/// Reads the token from the `redirect_url` Imgur redirected to after authorizing and stores it.
+///
+/// # Errors
+///
+/// This function will return an error if the url does not contain a token or the token can
+/// not be stored.
+pub fn login(redirect_url: &str) -> Result<Token, ImgurError> {
+    let token = parse_redirect_url(redirect_url, chrono::Utc::now().timestamp())?;
+    save_token(&token)?;
+    Ok(token)
+}
+
+/// Reads the token from the fragment of the `redirect_url`, which expires relative to `now`.
+fn parse_redirect_url(redirect_url: &str, now: i64) -> Result<Token, ImgurError> {
+    let params = redirect_url
+        .split_once('#')
+        .map(|(_, fragment)| fragment)
+        .ok_or(ImgurError::InvalidRedirectUrl)?;
+    let param = |name: &str| {
+        params
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| *key == name)
+            .ok_or(ImgurError::InvalidRedirectUrl)
+            .and_then(|(_, value)| percent_decode(value))
+    };
+
+    Ok(Token {
+        access_token: param("access_token")?,
+        refresh_token: param("refresh_token")?,
+        expires_at: now
+            + param("expires_in")?
+                .parse::<i64>()
+                .map_err(|_| ImgurError::InvalidRedirectUrl)?,
+        account_username: param("account_username")?,
+    })
+}
+
+/// Decodes the `%XX` escapes of a url parameter, e.g. in account names with special characters.
+fn percent_decode(value: &str) -> Result<String, ImgurError> {
+    let mut bytes = value.bytes();
+    let mut decoded = Vec::with_capacity(value.len());
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+        let mut digit = || {
+            bytes
+                .next()
+                .and_then(|digit| char::from(digit).to_digit(16))
+                .ok_or(ImgurError::InvalidRedirectUrl)
+        };
+        let byte = digit()? * 16 + digit()?;
+        decoded.push(byte as u8);
+    }
+    String::from_utf8(decoded).map_err(|_| ImgurError::InvalidRedirectUrl)
+}
+
+/// Removes the stored token, so uploads are anonymous again.
+///
+/// # Errors
+///
+/// This function will return an error if the token file exists but can not be removed.
+pub fn logout() -> Result<(), ImgurError> {
+    match fs::remove_file(token_file()?) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Loads the stored token, refreshing and storing it again if it is expired.
+///
+/// Returns `None` if there is no logged in account.
+///
+/// # Errors
+///
+/// This function will return an error if the token can not be read, refreshed or stored.
+/// Refreshing needs the client secret of the application in the `IMGUR_CLIENT_SECRET`
+/// environment variable.
+pub fn token(client: &Client) -> Result<Option<Token>, ImgurError> {
+    let token: Token = match fs::read_to_string(token_file()?) {
+        Ok(content) => serde_json::from_str(&content)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    if !token.is_expired() {
+        return Ok(Some(token));
+    }
+
+    let client_secret = env::var("IMGUR_CLIENT_SECRET").map_err(|_| ImgurError::NoClientSecret)?;
+    let res = client
+        .post("https://api.imgur.com/oauth2/token")
+        .form(&[
+            ("refresh_token", token.refresh_token.as_str()),
+            ("client_id", secrets::IMGUR_CLIENT_ID),
+            ("client_secret", client_secret.as_str()),
+            ("grant_type", "refresh_token"),
+        ])
+        .send()?;
+    let refreshed: RefreshResponse = check_status(res)?.json()?;
+
+    let token = Token {
+        access_token: refreshed.access_token,
+        refresh_token: refreshed.refresh_token,
+        expires_at: chrono::Utc::now().timestamp() + refreshed.expires_in,
+        account_username: refreshed.account_username,
+    };
+    save_token(&token)?;
+    Ok(Some(token))
+}
+
+/// Finds the id of the album with the `title` in the account of the `token`, creating a
+/// hidden album if there is none yet.
+///
+/// # Errors
+///
+/// This function will return an error if the albums can not be listed or created.
+pub fn album_id(client: &Client, token: &Token, title: &str) -> Result<String, ImgurError> {
+    let res = authorized(
+        client.get("https://api.imgur.com/3/account/me/albums"),
+        token,
+    )
+    .send()?;
+    let albums: DataResponse<Vec<Album>> = check_status(res)?.json()?;
+
+    if let Some(album) = albums
+        .data
+        .into_iter()
+        .find(|album| album.title.as_deref() == Some(title))
+    {
+        return Ok(album.id);
+    }
+
+    let res = authorized(client.post("https://api.imgur.com/3/album"), token)
+        .form(&[("title", title), ("privacy", "hidden")])
+        .send()?;
+    let album: DataResponse<Album> = check_status(res)?.json()?;
+    Ok(album.data.id)
+}
+
+/// Adds the authorization header for the `token` to the `request`.
+pub fn authorized(request: RequestBuilder, token: &Token) -> RequestBuilder {
+    request.header("Authorization", authorization(token))
+}
+
+/// The authorization header value for the `token`.
+pub fn authorization(token: &Token) -> String {
+    format!("Bearer {}", token.access_token)
+}
+
+fn save_token(token: &Token) -> Result<(), ImgurError> {
+    let path = token_file()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // The refresh token gives access to the account, so only the user may read it
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to new files, token files of older versions are restricted as well
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(serde_json::to_string_pretty(token)?.as_bytes())?;
+    Ok(())
+}
+
+fn check_status(
+    res: reqwest::blocking::Response,
+) -> Result<reqwest::blocking::Response, ImgurError> {
+    if res.status().is_success() {
+        Ok(res)
+    } else {
+        Err(ImgurError::Status {
+            code: res.status().as_u16(),
+            body: res.text().unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ImgurError {
+    #[error("no config directory found to store the Imgur token in")]
+    NoConfigDir,
+    #[error("the url does not contain an Imgur token")]
+    InvalidRedirectUrl,
+    #[error(
+        "the Imgur token expired, set IMGUR_CLIENT_SECRET to refresh it or log in again with `time-for login`"
+    )]
+    NoClientSecret,
+    #[error("uploading into an album requires an Imgur account, log in with `time-for login`")]
+    NotLoggedIn,
+    #[error("could not read or write the Imgur token")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+    #[error("the Imgur token or response is not valid json")]
+    Json {
+        #[from]
+        source: serde_json::Error,
+    },
+    #[error("There was an error with the Imgur request:\n{source:?}")]
+    Request {
+        #[from]
+        source: reqwest::Error,
+    },
+    #[error("Imgur responded with the error {code}: {body:?}")]
+    Status { code: u16, body: String },
+}
+
+#[derive(Deserialize)]
+struct RefreshResponse {
+    access_token: String,
+    refresh_token: String,
+    expires_in: i64,
+    account_username: String,
+}
+
+#[derive(Deserialize)]
+struct DataResponse<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct Album {
+    id: String,
+    title: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_token_of_the_redirect_url() {
+        let token = parse_redirect_url(
+            "https://example.com/callback#access_token=abc&expires_in=3600&token_type=bearer\
+             &refresh_token=def&account_username=someone&account_id=1",
+            1000,
+        )
+        .unwrap();
+        assert_eq!(token.access_token, "abc");
+        assert_eq!(token.refresh_token, "def");
+        assert_eq!(token.expires_at, 4600);
+        assert_eq!(token.account_username, "someone");
+    }
+
+    #[test]
+    fn decodes_the_redirect_url_params() {
+        let token = parse_redirect_url(
+            "https://example.com/callback#access_token=a%2Bb%3D&expires_in=60\
+             &refresh_token=c%2fd&account_username=J%C3%BCrgen%20M",
+            0,
+        )
+        .unwrap();
+        assert_eq!(token.access_token, "a+b=");
+        assert_eq!(token.refresh_token, "c/d");
+        assert_eq!(token.account_username, "Jürgen M");
+    }
+
+    #[test]
+    fn rejects_invalid_redirect_urls() {
+        for url in [
+            "https://example.com/callback?access_token=abc",
+            "https://example.com/callback#access_token=abc&expires_in=60&refresh_token=def",
+            "https://example.com/callback#access_token=a%2&expires_in=60\
+             &refresh_token=def&account_username=someone",
+            "https://example.com/callback#access_token=a%zz&expires_in=60\
+             &refresh_token=def&account_username=someone",
+            "https://example.com/callback#access_token=abc&expires_in=soon\
+             &refresh_token=def&account_username=someone",
+        ] {
+            assert!(
+                matches!(
+                    parse_redirect_url(url, 0),
+                    Err(ImgurError::InvalidRedirectUrl)
+                ),
+                "{url}"
+            );
+        }
+    }
+}
@@ -1,60 +1,32 @@
 use std::{
-    env::{current_dir, temp_dir},
-    ffi::OsStr,
-    fs,
-    io::{self, Cursor},
+    fmt, fs,
+    io::{self, Write},
     path::{Path, PathBuf},
     process::Command,
+    time::{Duration, Instant},
 };
 
-use anyhow::bail;
-use arboard::Clipboard;
-use chrono::Datelike;
-
-use ordinal::Ordinal;
-use serde::Deserialize;
-use spinners::{Spinner, Spinners};
+use progress::Stage;
 use tenor::TenorError;
-use tfc::{Context, Key, KeyboardContext};
 
+pub mod cache;
 pub mod clapper;
+pub mod clipboard;
 pub mod ffmpeg;
+pub mod ffprobe;
+mod generator;
+pub mod gifsicle;
+pub mod history;
+pub mod imgur;
+pub mod progress;
+pub mod report;
 mod secrets;
+pub mod storyboard;
 mod tenor;
+pub mod upload;
+pub mod webhook;
 
-struct MediaFile(PathBuf);
-
-impl MediaFile {
-    /// The given file but with "_text" added to the file name
-    pub fn with_text(&self) -> PathBuf {
-        self.add_to_file_name("_text")
-    }
-
-    /// The given file but with "_scaled" added to the file name
-    pub fn scaled(&self) -> PathBuf {
-        self.add_to_file_name("_scaled")
-    }
-
-    //
-    pub fn base(&self) -> PathBuf {
-        self.0.clone()
-    }
-
-    /// Adds the `addition` string to the file name of the `file`
-    fn add_to_file_name(&self, addition: &str) -> PathBuf {
-        self.0.with_file_name(
-            (self.0.file_stem().unwrap_or_default().to_string_lossy()
-                + addition
-                + "."
-                + self
-                    .0
-                    .extension()
-                    .unwrap_or_else(|| OsStr::new("webm"))
-                    .to_string_lossy())
-            .to_string(),
-        )
-    }
-}
+pub use generator::{GenerationRequest, GenerationResult, Generator};
 
 use thiserror::Error;
 #[derive(Error, Debug)]
@@ -66,8 +38,31 @@ pub enum TimeForError {
         #[from]
         source: ffmpeg::FfmpegError,
     },
-    #[error("could not create working directory")]
-    CreateWorkingDirectory { source: std::io::Error },
+    #[error("could not inspect media file")]
+    FfprobeError {
+        #[from]
+        source: ffprobe::FfprobeError,
+    },
+    #[error("the downloaded gif \"{}\" is not a valid video ({info})", file.display())]
+    InvalidDownload {
+        file: PathBuf,
+        info: ffprobe::MediaInfo,
+    },
+    #[error("the clip \"{}\" is only {duration:.2}s long and can't start at {start:.2}s", file.display())]
+    StartOutOfRange {
+        file: PathBuf,
+        start: f64,
+        duration: f64,
+    },
+    #[error("the transition duration must be larger than 0, not {duration}")]
+    InvalidTransitionDuration { duration: f64 },
+    #[error("`--json` can not be used with `--output -`, since both are written to stdout")]
+    JsonToStdout,
+    #[error("could not set up the working directory")]
+    Cache {
+        #[from]
+        source: cache::CacheError,
+    },
     #[error("there was an error with a file")]
     Io {
         // #[from]
@@ -88,143 +83,245 @@ pub enum TimeForError {
         #[from]
         source: reqwest::Error,
     },
-    #[error("the gifs could not be scaled correctly!\nffmpeg exit code ({})", exit_code.map_or("None".to_string(), |c| c.to_string()))]
-    ScalingError { exit_code: Option<i32> },
-    #[error("no relative directory found or non sufficient permissions")]
-    NoRelativePath { source: std::io::Error },
+    #[error("the file is {:.1} MB and could not be re-encoded to fit the limit of {:.1} MB", *size as f64 / MB, *limit as f64 / MB)]
+    TooLarge { size: u64, limit: u64 },
+    #[error("could not upload or delete the file")]
+    Upload {
+        #[from]
+        source: upload::UploadError,
+    },
+    #[error("could not use the Imgur account")]
+    Imgur {
+        #[from]
+        source: imgur::ImgurError,
+    },
+    #[error("could not access the upload history")]
+    History {
+        #[from]
+        source: history::HistoryError,
+    },
+    #[error("no upload found for \"{target}\" in the upload history")]
+    UploadNotFound { target: String },
+    #[error("could not compress the GIF")]
+    Gifsicle {
+        #[from]
+        source: gifsicle::GifsicleError,
+    },
+    #[error("could not load the storyboard")]
+    Storyboard {
+        #[from]
+        source: storyboard::StoryboardError,
+    },
 }
 
-pub fn run(clap_args: clapper::Args) -> Result<(), TimeForError> {
-    println!("TIME FOR");
-
-    // let clap_args = clapper::Inputs::parse();
-
-    if !ffmpeg::is_available() {
-        return Err(TimeForError::FfmpegNotFound);
-    }
-
-    let query = &clap_args.query;
+/// The number of bytes in a MB, as used by Imgur and Discord for their limits
+const MB: f64 = 1024.0 * 1024.0;
 
-    let temp = temp_dir();
-    let work_dir;
-    if !clap_args.relative {
-        work_dir = temp.join("time-for");
-    } else {
-        work_dir = current_dir()
-            .map_err(|e| TimeForError::NoRelativePath { source: e })?
-            .join("time-for");
+pub fn run(clap_args: clapper::Args) -> Result<(), TimeForError> {
+    if let Some(command) = clap_args.command {
+        return run_command(command);
     }
 
-    let query_file = MediaFile(work_dir.join("query.webm"));
-    let look_at_time_file = MediaFile(work_dir.join("look_at_time.webm"));
-
-    let final_output = work_dir.join("full.webm");
-
-    // ?: Is the check even needed?
-    fs::create_dir_all(work_dir).map_err(|e| TimeForError::CreateWorkingDirectory { source: e })?;
-
-    ///// TODO: Maybe use https://crates.io/crates/indicatif instead
-    // TODO: Look for a way to remove Spinner on error
-    let mut sp = Spinner::with_timer(Spinners::Arc, "Creating GIF".into());
-
-    //* Download a random gif
-    if let Some(query) = query {
-        let random_webm_url = tenor::random_webm(query, Some(clap_args.considered_gifs))?;
-        download_file(&random_webm_url, &query_file.base())?;
+    // A bot reading the report still gets JSON if the run fails
+    let json = clap_args.json;
+    let res = generate(clap_args);
+    if let (true, Err(e)) = (json, &res) {
+        let json = serde_json::to_string(&report::ErrorReport::new(e))
+            .expect("the report only has serializable fields");
+        println!("{}", json);
     }
+    res
+}
 
-    let random_look_at_time_webm_url = tenor::random_webm("look at time", Some(16))?;
-    download_file(&random_look_at_time_webm_url, &look_at_time_file.base())?;
-
-    //* Scale to same size
-    let mut handles = vec![];
-
-    if query.is_some() {
-        handles.push(ffmpeg::scale(
-            &query_file.base(),
-            None,
-            &query_file.scaled(),
-        ));
+/// Generates the file of the CLI arguments and shares it as requested.
+fn generate(clap_args: clapper::Args) -> Result<(), TimeForError> {
+    // With `--output -` the file is written to stdout, so everything else goes to stderr
+    let to_stdout = clap_args.output.as_deref() == Some(Path::new("-"));
+    if to_stdout && clap_args.json {
+        return Err(TimeForError::JsonToStdout);
     }
+    // The same goes for the report with `--json`
+    let stdout_taken = to_stdout || clap_args.json;
+    status(stdout_taken, "TIME FOR");
 
-    handles.push(ffmpeg::scale(
-        &look_at_time_file.base(),
-        None,
-        &look_at_time_file.scaled(),
-    ));
+    // let clap_args = clapper::Inputs::parse();
 
-    for handle in handles {
-        let output = handle.unwrap().wait_with_output()?;
+    let query = &clap_args.query;
 
-        // TODO: Make this error handling better
-        if !output.status.success() {
-            return Err(TimeForError::ScalingError {
-                exit_code: output.status.code(),
-            });
+    // Resolve the Imgur account before creating the GIF, so it doesn't fail afterwards
+    let mut warnings = vec![];
+    let mut upload_options = upload::UploadOptions {
+        timeout: Duration::from_secs(clap_args.upload_timeout),
+        retries: clap_args.upload_retries,
+        ..Default::default()
+    };
+    let mut uploaders = match clap_args.no_upload {
+        true => vec![],
+        false => clap_args.uploaders.clone(),
+    };
+    if uploaders.contains(&upload::Uploader::Imgur) {
+        match imgur_account(clap_args.album.as_deref()) {
+            Ok((token, album)) => {
+                upload_options.imgur_token = token;
+                upload_options.imgur_album = album;
+            }
+            Err(e @ imgur::ImgurError::NotLoggedIn) => return Err(e.into()),
+            // Imgur may only be one of the uploaders, the others can still be tried
+            Err(e) => {
+                warn(
+                    &mut warnings,
+                    format!("Imgur is skipped, the account could not be used: {}", e),
+                );
+                uploaders.retain(|uploader| *uploader != upload::Uploader::Imgur);
+            }
         }
     }
 
-    //* Create text for gif
-    let time = chrono::Local::now() + chrono::Duration::seconds(clap_args.delay as i64);
-    let day_ord = Ordinal(time.day()).to_string();
-    let format_str = format!("It is %H:%M:%S %A %B {day_ord} %Y");
-    let text = time.format(&format_str);
-
-    let mut handles = vec![];
-
-    //* Add text to time gif
-    handles.push(ffmpeg::add_text(
-        &look_at_time_file.scaled(),
-        &text.to_string(),
-        &look_at_time_file.with_text(),
-    ));
-
-    if let Some(query) = query {
-        let query_text = clap_args
-            .custom_text
-            .unwrap_or(format!("time for {}", query));
-
-        // Add text to query gif
-        handles.push(ffmpeg::add_text(
-            &query_file.scaled(),
-            &query_text,
-            &query_file.with_text(),
-        ));
+    let storyboard = match &clap_args.storyboard {
+        Some(file) => storyboard::Storyboard::load(file)?,
+        None => default_storyboard(&clap_args),
+    };
+    let output = clap_args
+        .output
+        .as_ref()
+        .filter(|_| !to_stdout)
+        .map(|output| {
+            output_path(
+                output,
+                &clap_args.output_name,
+                query.as_deref(),
+                clap_args.format,
+            )
+        });
+
+    let mut request = GenerationRequest::new(storyboard)
+        .format(clap_args.format)
+        .gif(gif_options(&clap_args))
+        .normalization(ffmpeg::Normalization {
+            width: clap_args.size.0,
+            height: clap_args.size.1,
+            fit: clap_args.fit,
+            ..Default::default()
+        })
+        .transition(ffmpeg::Transition {
+            kind: clap_args.transition,
+            duration: clap_args.transition_duration,
+        })
+        .considered_gifs(clap_args.considered_gifs)
+        .delay(clap_args.delay)
+        .upload(uploaders, upload_options)
+        .cache_dir(cache::base_dir(clap_args.relative)?)
+        // The whole working directory is removed after writing the file to stdout
+        .keep_intermediates(clap_args.keep_intermediates || to_stdout);
+    if let Some(max_size) = size_limit(&clap_args) {
+        request = request.max_size(max_size);
     }
-
-    for handle in handles {
-        handle
-            .unwrap()
-            .wait()
-            .expect("Add text command wasn't running");
+    if let Some(output) = output {
+        request = request.output(output);
     }
-
-    //* Stitch gifs
-    if query.is_some() {
-        ffmpeg::stitch_files_concat_demuxer(
-            &look_at_time_file.with_text(),
-            &query_file.with_text(),
-            &final_output,
-        )?;
-    } else {
-        fs::rename(&look_at_time_file.with_text(), &final_output)
-            .expect("Rename look_at_time_text_scaled to final_output");
+    let mut bars = progress::Bars::default();
+    let result = Generator::new()
+        .on_progress(move |progress| bars.update(progress))
+        .generate(&request)?;
+    let mut report = report::RunReport::from(&result);
+    report.warnings.splice(0..0, warnings);
+    report.path = report.path.filter(|_| !to_stdout);
+    let final_output = &result.path;
+    let caption = &result.caption;
+
+    //* Print the link of the upload
+    let link = result.upload.as_ref().map(|upload| upload.link.clone());
+    if let Some(upload) = &result.upload {
+        if let Err(e) = history::add(upload) {
+            warn(
+                &mut report.warnings,
+                format!("The upload could not be added to the history: {}", e),
+            );
+        }
     }
-
-    //* Upload file to imgur
     if !clap_args.no_upload {
-        let res = upload_video_to_imgur(&final_output);
+        match &link {
+            Some(link) => status(stdout_taken, link),
+            None => {
+                eprintln!(
+                    "There was an error uploading the file, so here is the file path instead:"
+                );
+                // Print path to output file
+                let can_path = final_output.as_os_str().to_string_lossy();
+                eprintln!("{}", &can_path);
+            }
+        }
+    }
 
-        sp.stop_with_newline();
+    //* Paste the link, or the file itself if it isn't uploaded
+    let absolute_output = std::path::absolute(final_output).unwrap_or(final_output.clone());
+    let file_link = match clap_args.no_upload {
+        true => Some(clipboard::file_uri(&absolute_output)),
+        false => None,
+    };
+    let text = link
+        .as_ref()
+        .or(file_link.as_ref())
+        .map(|link| clap_args.copy_as.format(link, &absolute_output, caption));
+    let content = match text.as_deref() {
+        // The file is removed after streaming it to stdout, so only a link is worth copying
+        _ if to_stdout && link.is_none() => None,
+        // The file is pasted as is, unless it is wanted in another format
+        Some(_) if link.is_none() && clap_args.copy_as == clipboard::CopyFormat::Link => {
+            Some(clipboard::Content::File(final_output))
+        }
+        Some(text) => Some(clipboard::Content::Text(text)),
+        None => None,
+    };
+    if let Some(content) = content {
+        // Only paste into the focused window if nothing is posted to a webhook or piped on
+        let paste_mode = match clap_args.paste {
+            clipboard::PasteMode::Type if !clap_args.webhooks.is_empty() || to_stdout => {
+                clipboard::PasteMode::Clipboard
+            }
+            paste_mode => paste_mode,
+        };
+        let pasted = match paste_mode {
+            clipboard::PasteMode::None => Ok(()),
+            clipboard::PasteMode::Clipboard => clipboard::copy(&content, clap_args.primary),
+            clipboard::PasteMode::Type => clipboard::paste(&content, clap_args.primary),
+        };
+        if let Err(e) = pasted {
+            warn(
+                &mut report.warnings,
+                format!(
+                    "Could not paste the {}: {}",
+                    if link.is_some() { "link" } else { "file" },
+                    e
+                ),
+            );
+        }
+    }
 
-        if output_and_paste(res).is_err() {
-            eprintln!("There was an error uploading to imgur, so here is the file path instead:");
-            // Print path to output file
-            let can_path = final_output.as_os_str().to_string_lossy();
-            eprintln!("{}", &can_path);
+    //* Post to the webhooks
+    if !clap_args.webhooks.is_empty() {
+        let template = clap_args.webhook_message.as_deref().unwrap_or(match query {
+            Some(_) => "time for {query}!",
+            None => "{text}",
+        });
+        let message = webhook::render_message(
+            template,
+            query.as_deref().unwrap_or_default(),
+            caption,
+            link.as_deref().unwrap_or_default(),
+        );
+
+        // A failing webhook doesn't stop the others, the file is already created and uploaded
+        let started = Instant::now();
+        for url in &clap_args.webhooks {
+            if let Err(e) = webhook::post(url, &message, link.as_deref(), final_output) {
+                warn(
+                    &mut report.warnings,
+                    format!("Could not post to the webhook: {}", e),
+                );
+            }
         }
-    } else {
-        sp.stop_with_newline();
+        report.add_timing(Stage::Webhook, started.elapsed());
     }
 
     //* Open output folder in windows explorer if requested with "o" or "open"
@@ -234,68 +331,341 @@ pub fn run(clap_args: clapper::Args) -> Result<(), TimeForError> {
     }
 
     if clap_args.open {
-        open::that(&final_output).expect("Open the file");
+        open::that(final_output).expect("Open the file");
     }
 
-    println!("DONE DONE DONE DONE DONE DONE");
+    //* Stream the file to stdout and remove it afterwards, unless the intermediates are kept
+    if to_stdout {
+        let mut file = fs::File::open(final_output).map_err(|e| TimeForError::Io { source: e })?;
+        let mut stdout = io::stdout().lock();
+        io::copy(&mut file, &mut stdout)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| TimeForError::Io { source: e })?;
+
+        if !clap_args.keep_intermediates {
+            if let Err(e) = fs::remove_dir_all(&result.work_dir) {
+                warn(
+                    &mut report.warnings,
+                    format!("The intermediate files could not be removed: {}", e),
+                );
+            }
+        }
+    }
+
+    status(stdout_taken, "DONE DONE DONE DONE DONE DONE");
+    if clap_args.json {
+        let json = serde_json::to_string(&report).expect("the report only has serializable fields");
+        println!("{}", json);
+    }
     Ok(())
 }
 
-fn download_file(url: &str, file_path: &std::path::Path) -> Result<(), TimeForError> {
-    let res = reqwest::blocking::get(url)?;
-    let mut random_webm_file = fs::File::create(file_path)?;
-    let mut content = Cursor::new(res.bytes()?);
-    // let content = res.text()?;
-    io::copy(&mut content, &mut random_webm_file)?;
-    Ok(())
+/// The path of the final file for the `--output` path.
+///
+/// If the `output` is a directory, the file is named after the `template` with `{date}`,
+/// `{time}`, `{query}` and `{ext}` replaced, otherwise the `output` is used as is.
+fn output_path(
+    output: &Path,
+    template: &str,
+    query: Option<&str>,
+    format: ffmpeg::OutputFormat,
+) -> PathBuf {
+    let is_dir = output.is_dir() || output.as_os_str().to_string_lossy().ends_with(['/', '\\']);
+    if !is_dir {
+        return output.to_path_buf();
+    }
+
+    // Only keep characters which are allowed in file names on all platforms
+    let query: String = query
+        .unwrap_or("time")
+        .chars()
+        .map(|c| match c.is_alphanumeric() || c == '-' {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    let now = chrono::Local::now();
+    let name = template
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H-%M-%S").to_string())
+        .replace("{query}", &query)
+        .replace("{ext}", format.extension());
+    output.join(name)
 }
 
-fn upload_video_to_imgur(file_path: &Path) -> reqwest::blocking::Response {
-    let imgur_api = "https://api.imgur.com/3/upload";
-    // let file = File::open(&file_path).expect("Open file");
-    let form = reqwest::blocking::multipart::Form::new()
-        .file("video", file_path)
-        .expect("Create form with file");
+/// The storyboard of a run without a `--storyboard`: a "look at time" clip with the current
+/// time followed by the clip of the query, if there is one, with the clip options.
+fn default_storyboard(clap_args: &clapper::Args) -> storyboard::Storyboard {
+    let mut storyboard =
+        storyboard::Storyboard::time_for(clap_args.query.as_deref(), clap_args.custom_text.clone());
+
+    // The clip options are meant for the query clip
+    let clip = storyboard
+        .segments
+        .last_mut()
+        .expect("there is always the look at time clip");
+    clip.start = clap_args.start;
+    clip.duration = clap_args.max_duration;
+    clip.speed = clap_args.speed;
+    clip.reverse = Some(clap_args.reverse);
+    clip.boomerang = Some(clap_args.boomerang);
+    clip.min_duration = clap_args.min_duration;
+
+    storyboard
+}
 
-    let client = reqwest::blocking::Client::new();
-    let res = client
-        .post(imgur_api)
-        .header(
-            "Authorization",
-            format!("Client-ID {}", secrets::IMGUR_CLIENT_ID),
-        )
-        .multipart(form)
-        .send()
-        .unwrap();
-    res
+/// The GIF options of the `--quality` preset with the single options given overriding it.
+fn gif_options(clap_args: &clapper::Args) -> ffmpeg::GifOptions {
+    let mut gif = clap_args.quality.options();
+    if let Some(fps) = clap_args.gif_fps {
+        gif.fps = Some(fps).filter(|fps| *fps > 0);
+    }
+    if let Some(colors) = clap_args.colors {
+        gif.max_colors = colors;
+    }
+    if let Some(dither) = clap_args.dither {
+        gif.dither = dither;
+    }
+    if let Some(stats_mode) = clap_args.stats_mode {
+        gif.stats_mode = stats_mode;
+    }
+    if clap_args.per_frame_palette {
+        gif.per_frame_palette = true;
+    }
+    if let Some(lossy) = clap_args.lossy {
+        gif.lossy = Some(lossy).filter(|lossy| *lossy > 0);
+    }
+    gif
 }
 
-fn output_and_paste(res: reqwest::blocking::Response) -> anyhow::Result<()> {
-    if let Ok(resp) = res.json::<Response>() {
-        // Remove the dot '.' at the end of the link when uploading webm
-        let link = resp.data.link.trim_end_matches('.');
-
-        let mut clipboard = Clipboard::new().expect("Create new clipboard");
-        let _ = clipboard.set_text(<&str>::clone(&link));
-
-        let mut ctx = Context::new()?;
-        ctx.key_down(Key::Control)?;
-        ctx.key_click(Key::V)?;
-        ctx.key_up(Key::Control)?;
-        println!("{}", link);
-        return Ok(());
-    } else {
-        bail!("Output and paste error");
+/// The size limit in bytes for the final file, based on the `--max-size` or the targets the
+/// file is sent to.
+fn size_limit(clap_args: &clapper::Args) -> Option<u64> {
+    if let Some(max_size) = clap_args.max_size {
+        return Some((max_size * MB) as u64);
     }
+
+    let upload_limit = clap_args
+        .uploaders
+        .iter()
+        .map(|uploader| uploader.max_size(clap_args.format))
+        .filter(|_| !clap_args.no_upload)
+        .min();
+    // Files are only attached to webhooks if they are not uploaded
+    let webhook_limit = clap_args
+        .webhooks
+        .iter()
+        .filter_map(|url| webhook::WebhookKind::from_url(url).max_file_size())
+        .filter(|_| clap_args.no_upload)
+        .min();
+
+    upload_limit.into_iter().chain(webhook_limit).min()
 }
 
-#[derive(Deserialize)]
-struct Response {
-    data: Data,
+fn run_command(command: clapper::Command) -> Result<(), TimeForError> {
+    match command {
+        clapper::Command::Delete { target, last } => {
+            let upload = if last {
+                history::last()?
+            } else {
+                history::find(target.as_deref().unwrap_or_default())?
+            };
+            let upload = upload.ok_or_else(|| TimeForError::UploadNotFound {
+                target: target.unwrap_or_else(|| "--last".to_string()),
+            })?;
+
+            // Only Imgur uploads of an account need the token, anonymous ones are deleted with
+            // the Client-ID
+            let imgur_token = match upload.uploader {
+                upload::Uploader::Imgur => imgur::token(&reqwest::blocking::Client::new())
+                    .unwrap_or_else(|e| {
+                        eprintln!("The Imgur token could not be loaded: {}", e);
+                        None
+                    }),
+                _ => None,
+            };
+            let options = upload::UploadOptions {
+                imgur_token,
+                ..Default::default()
+            };
+            upload::delete(&upload, &options)?;
+            history::remove(&upload.link)?;
+            println!("Deleted {}", upload.link);
+        }
+        clapper::Command::Search {
+            query,
+            considered_gifs,
+        } => {
+            for gif in tenor::search(&query, considered_gifs)? {
+                match ffprobe::probe(&gif.url) {
+                    Ok(info) => println!("{}  {}  {}", gif.id, info, gif.url),
+                    Err(e) => println!("{}  ({})  {}", gif.id, e, gif.url),
+                }
+            }
+        }
+        clapper::Command::Preview { input } => {
+            let info = ffprobe::probe(&input)?;
+            println!("{}", input);
+            println!("  resolution: {}x{}", info.width, info.height);
+            println!("  frame rate: {:.2} fps", info.fps);
+            println!("  duration:   {:.2} s", info.duration);
+            match &info.pixel_format {
+                Some(pixel_format) => println!("  codec:      {} ({})", info.codec, pixel_format),
+                None => println!("  codec:      {}", info.codec),
+            }
+            println!(
+                "  audio:      {}",
+                if info.has_audio { "yes" } else { "no" }
+            );
+        }
+        clapper::Command::Login => {
+            let url = imgur::authorize_url();
+            println!(
+                "Log in to Imgur and paste the address you are redirected to:\n{}",
+                url
+            );
+            let _ = open::that(&url);
+
+            let mut redirect_url = String::new();
+            io::stdin().read_line(&mut redirect_url)?;
+            let token = imgur::login(redirect_url.trim())?;
+            println!("Logged in as {}", token.account_username);
+        }
+        clapper::Command::Logout => {
+            imgur::logout()?;
+            println!("Logged out of Imgur");
+        }
+        clapper::Command::Cache {
+            command:
+                clapper::CacheCommand::Clean {
+                    older_than,
+                    relative,
+                },
+        } => {
+            let base = cache::base_dir(relative)?;
+            let summary = cache::clean(
+                &base,
+                Duration::from_secs(older_than.saturating_mul(60 * 60)),
+            )?;
+            println!(
+                "Removed {} runs ({:.1} MB) from {}",
+                summary.runs,
+                summary.bytes as f64 / MB,
+                base.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Prints a status `message` to stdout, or to stderr if stdout is used for the file itself or
+/// the JSON report.
+fn status(stdout_taken: bool, message: impl fmt::Display) {
+    match stdout_taken {
+        true => eprintln!("{}", message),
+        false => println!("{}", message),
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct Data {
-    link: String,
+/// Loads the Imgur token and finds the id of the `album`, if one is wanted.
+fn imgur_account(
+    album: Option<&str>,
+) -> Result<(Option<imgur::Token>, Option<String>), imgur::ImgurError> {
+    let client = reqwest::blocking::Client::new();
+    let token = imgur::token(&client)?;
+    let album = match album {
+        Some(title) => {
+            let token = token.as_ref().ok_or(imgur::ImgurError::NotLoggedIn)?;
+            Some(imgur::album_id(&client, token, title)?)
+        }
+        None => None,
+    };
+    Ok((token, album))
 }
 
+/// Prints the `warning` about a problem which doesn't stop the run to stderr and adds it to
+/// the `warnings` of the report.
+fn warn(warnings: &mut Vec<String>, warning: String) {
+    eprintln!("{}", warning);
+    warnings.push(warning);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_files_are_used_as_is() {
+        assert_eq!(
+            output_path(
+                Path::new("gifs/coffee.gif"),
+                "{query}.{ext}",
+                Some("coffee"),
+                ffmpeg::OutputFormat::Webm
+            ),
+            Path::new("gifs/coffee.gif")
+        );
+    }
+
+    #[test]
+    fn output_dirs_get_the_file_name_of_the_template() {
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            output_path(
+                &dir,
+                "time-for-{query}.{ext}",
+                Some("coffee"),
+                ffmpeg::OutputFormat::Webm
+            ),
+            dir.join("time-for-coffee.webm")
+        );
+        // Directories which don't exist yet are recognized by the trailing separator
+        assert_eq!(
+            output_path(
+                Path::new("new-gifs/"),
+                "{query}.{ext}",
+                Some("tea"),
+                ffmpeg::OutputFormat::Gif
+            ),
+            Path::new("new-gifs/tea.gif")
+        );
+    }
+
+    #[test]
+    fn output_names_only_contain_safe_characters() {
+        assert_eq!(
+            output_path(
+                Path::new("gifs/"),
+                "{query}.{ext}",
+                Some("a/b: c's tea?"),
+                ffmpeg::OutputFormat::Gif
+            ),
+            Path::new("gifs/a_b__c_s_tea_.gif")
+        );
+        assert_eq!(
+            output_path(
+                Path::new("gifs/"),
+                "{query}.{ext}",
+                None,
+                ffmpeg::OutputFormat::Mp4
+            ),
+            Path::new("gifs/time.mp4")
+        );
+    }
+
+    #[test]
+    fn output_names_contain_the_date_and_time() {
+        let path = output_path(
+            Path::new("gifs/"),
+            "{date}_{time}",
+            None,
+            ffmpeg::OutputFormat::Gif,
+        );
+        let name = path.file_name().unwrap().to_string_lossy();
+        // e.g. 2024-01-31_12-30-00
+        assert_eq!(name.len(), 19, "{name}");
+        assert!(name
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '-' || c == '_'));
+    }
+}
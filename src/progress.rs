@@ -0,0 +1,109 @@
+use std::{fmt, time::Duration};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+/// The stages of creating and sending a GIF, each shown with its own progress bar.
+///
+/// The clips are normalized, captioned and stitched in a single ffmpeg pass, which is
+/// reported as [`Stage::Encode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Download,
+    Encode,
+    Upload,
+    Webhook,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Download => write!(f, "download"),
+            Stage::Encode => write!(f, "encode"),
+            Stage::Upload => write!(f, "upload"),
+            Stage::Webhook => write!(f, "webhook"),
+        }
+    }
+}
+
+/// Creates the progress bar for the `stage` with the `len` in bytes or milliseconds.
+///
+/// Without a `len` the bar is a spinner, since the progress of the stage is unknown.
+pub fn stage_bar(stage: Stage, len: Option<u64>) -> ProgressBar {
+    let bar = match len {
+        Some(len) => ProgressBar::new(len).with_style(
+            ProgressStyle::with_template("{prefix:>9} [{bar:30}] {percent:>3}% {msg}")
+                .expect("valid progress bar template")
+                .progress_chars("=> "),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{prefix:>9} {spinner} {elapsed} {msg}")
+                .expect("valid spinner template"),
+        ),
+    };
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar.with_prefix(stage.to_string())
+}
+
+/// A step of [`Generator::generate()`](crate::Generator::generate).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// The `stage` started, with the `total` in bytes for downloads or in milliseconds of
+    /// output for encoding, `None` if it is unknown
+    Started {
+        stage: Stage,
+        total: Option<u64>,
+        message: String,
+    },
+    /// The running `stage` reached the `position` of its total
+    Advanced {
+        stage: Stage,
+        position: u64,
+        message: Option<String>,
+    },
+    Finished {
+        stage: Stage,
+    },
+    /// A problem which doesn't stop the generation, e.g. a failed upload
+    Warning(String),
+}
+
+/// Shows the [`Progress`] of a generation with a progress bar for every stage and prints the
+/// warnings to stderr.
+#[derive(Debug, Default)]
+pub struct Bars {
+    bar: Option<ProgressBar>,
+}
+
+impl Bars {
+    pub fn update(&mut self, progress: &Progress) {
+        match progress {
+            Progress::Started {
+                stage,
+                total,
+                message,
+            } => {
+                let bar = stage_bar(*stage, *total);
+                bar.set_message(message.clone());
+                self.bar = Some(bar);
+            }
+            Progress::Advanced {
+                position, message, ..
+            } => {
+                if let Some(bar) = &self.bar {
+                    bar.set_position(*position);
+                    if let Some(message) = message {
+                        bar.set_message(message.clone());
+                    }
+                }
+            }
+            Progress::Finished { .. } => {
+                if let Some(bar) = self.bar.take() {
+                    bar.finish();
+                }
+            }
+            Progress::Warning(warning) => eprintln!("{}", warning),
+        }
+    }
+}
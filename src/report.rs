@@ -0,0 +1,131 @@
+use std::{error::Error, path::PathBuf, time::Duration};
+
+use serde::Serialize;
+
+use crate::{ffmpeg::OutputFormat, progress::Stage, GenerationResult};
+
+/// The machine-readable summary of a run, printed as JSON with `--json`.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RunReport {
+    /// The link of the upload, `None` if it wasn't uploaded
+    pub link: Option<String>,
+    /// The uploader the file was uploaded to
+    pub uploader: Option<String>,
+    /// The local path of the final file, `None` if it was written to stdout
+    pub path: Option<PathBuf>,
+    /// The extension of the file format
+    pub format: String,
+    /// The size of the final file in bytes
+    pub size: u64,
+    /// The duration of the final file in seconds
+    pub duration: f64,
+    pub segments: Vec<SegmentReport>,
+    /// How long every stage took, in the order they ran
+    pub stages: Vec<StageTiming>,
+    /// The problems which didn't stop the run, e.g. a failed paste
+    pub warnings: Vec<String>,
+}
+
+/// The summary of a failed run, printed as JSON with `--json` instead of the [`RunReport`].
+#[derive(Serialize, Debug, Clone)]
+pub struct ErrorReport {
+    /// The error with all of its causes, separated by `: `
+    pub error: String,
+}
+
+impl ErrorReport {
+    /// Creates the report of the `error`.
+    pub fn new(error: &dyn Error) -> Self {
+        let mut message = error.to_string();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            message += &format!(": {}", cause);
+            source = cause.source();
+        }
+        Self { error: message }
+    }
+}
+
+/// One clip of the final file.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SegmentReport {
+    /// The query the GIF was searched with, `None` for local files
+    pub query: Option<String>,
+    /// The Tenor id of the chosen GIF
+    pub gif_id: Option<String>,
+    /// The url of the chosen GIF, or the path of a local file
+    pub source: String,
+    /// The text drawn on the clip
+    pub caption: String,
+    /// The duration of the clip in seconds, after trimming and applying the effects
+    pub duration: f64,
+}
+
+/// How long a stage of the run took.
+#[derive(Serialize, Debug, Clone)]
+pub struct StageTiming {
+    pub stage: Stage,
+    pub seconds: f64,
+}
+
+impl RunReport {
+    /// Creates an empty report for a file in the `format`.
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format: format.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Records that the `stage` took `elapsed`, adding to the time of the stage if it ran
+    /// before.
+    pub fn add_timing(&mut self, stage: Stage, elapsed: Duration) {
+        add_timing(&mut self.stages, stage, elapsed);
+    }
+}
+
+impl From<&GenerationResult> for RunReport {
+    fn from(result: &GenerationResult) -> Self {
+        Self {
+            link: result.upload.as_ref().map(|upload| upload.link.clone()),
+            uploader: result
+                .upload
+                .as_ref()
+                .map(|upload| upload.uploader.to_string()),
+            path: Some(result.path.clone()),
+            format: result.format.to_string(),
+            size: result.size,
+            duration: result.duration,
+            segments: result.segments.clone(),
+            stages: result.stages.clone(),
+            warnings: result.warnings.clone(),
+        }
+    }
+}
+
+/// Adds the `elapsed` time of the `stage` to the `stages`, adding to the time of the stage if
+/// it ran before.
+pub(crate) fn add_timing(stages: &mut Vec<StageTiming>, stage: Stage, elapsed: Duration) {
+    match stages.iter_mut().find(|timing| timing.stage == stage) {
+        Some(timing) => timing.seconds += elapsed.as_secs_f64(),
+        None => stages.push(StageTiming {
+            stage,
+            seconds: elapsed.as_secs_f64(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ffprobe::FfprobeError, TimeForError};
+
+    #[test]
+    fn error_report_contains_all_causes() {
+        let error = TimeForError::from(FfprobeError::NotFound);
+        assert_eq!(
+            serde_json::to_string(&ErrorReport::new(&error)).unwrap(),
+            r#"{"error":"could not inspect media file: ffprobe can not be found in path"}"#
+        );
+    }
+}
@@ -0,0 +1,447 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::ffmpeg::TransitionKind;
+
+/// An ordered list of clips the final file is composed of, read from a TOML or YAML file.
+///
+/// # Examples
+///
+/// ```toml
+/// transition = "crossfade"
+///
+/// [[segment]]
+/// query = "look at time"
+/// caption = "{time}"
+/// considered_gifs = 16
+///
+/// [[segment]]
+/// query = "coffee"
+/// duration = 3.0
+/// boomerang = true
+///
+/// [[segment]]
+/// file = "back_to_work.webm"
+/// caption = "back to work"
+/// transition = "wipe"
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Storyboard {
+    /// The transition between all segments which don't have their own
+    pub transition: Option<TransitionKind>,
+    /// The duration of the transitions in seconds
+    pub transition_duration: Option<f64>,
+    #[serde(rename = "segment", alias = "segments", default)]
+    pub segments: Vec<StoryboardSegment>,
+}
+
+/// One clip of a [`Storyboard`], either a GIF searched with a `query` or a local `file`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct StoryboardSegment {
+    /// The query to search a random GIF with
+    pub query: Option<String>,
+    /// A local video file, relative to the storyboard file
+    pub file: Option<PathBuf>,
+    /// The text drawn on the clip, see [`StoryboardSegment::caption()`]
+    pub caption: Option<String>,
+    /// The number of GIFs a random one is picked from for the `query`
+    pub considered_gifs: Option<u8>,
+    /// The time in seconds the clip starts at
+    pub start: Option<f64>,
+    /// The maximum length of the clip in seconds, defaults to the rest of the clip
+    #[serde(alias = "max_duration")]
+    pub duration: Option<f64>,
+    /// The factor the clip is sped up by
+    pub speed: Option<f64>,
+    /// Play the clip backwards
+    pub reverse: Option<bool>,
+    /// Play the clip forwards and then backwards
+    pub boomerang: Option<bool>,
+    /// Repeat the clip until it is at least this long in seconds
+    pub min_duration: Option<f64>,
+    /// The transition from the previous segment
+    pub transition: Option<TransitionKind>,
+    /// The duration of the transition from the previous segment in seconds
+    pub transition_duration: Option<f64>,
+}
+
+impl StoryboardSegment {
+    /// The text drawn on the clip with `{query}` and `{time}` (the current time in the form of
+    /// "It is 12:00:00 ...") replaced.
+    ///
+    /// Defaults to "time for {query}" for GIFs searched with a query and no text for files.
+    pub fn caption(&self, time: &str) -> String {
+        let template = match (&self.caption, &self.query) {
+            (Some(caption), _) => caption.as_str(),
+            (None, Some(_)) => "time for {query}",
+            (None, None) => "",
+        };
+        template
+            .replace("{query}", self.query.as_deref().unwrap_or_default())
+            .replace("{time}", time)
+    }
+}
+
+impl Storyboard {
+    /// The storyboard of the CLI without a `--storyboard`: a "look at time" clip with the
+    /// current time followed by a clip of the `query` with the `caption`, if there is a query.
+    pub fn time_for(query: Option<&str>, caption: Option<String>) -> Storyboard {
+        let mut segments = vec![StoryboardSegment {
+            query: Some("look at time".to_string()),
+            caption: Some("{time}".to_string()),
+            considered_gifs: Some(16),
+            ..Default::default()
+        }];
+        if let Some(query) = query {
+            segments.push(StoryboardSegment {
+                query: Some(query.to_string()),
+                caption,
+                ..Default::default()
+            });
+        }
+        Storyboard {
+            segments,
+            ..Default::default()
+        }
+    }
+
+    /// Reads the storyboard from the `file`, which is parsed as YAML if it ends with `.yaml` or
+    /// `.yml` and as TOML otherwise.
+    ///
+    /// The paths of the segment files are made relative to the directory of the `file`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file could not be read or parsed, or the
+    /// storyboard is not [valid](Storyboard::validate).
+    pub fn load(file: &Path) -> Result<Storyboard, StoryboardError> {
+        let content = fs::read_to_string(file)?;
+        let is_yaml = file
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml");
+        let mut storyboard: Storyboard = if is_yaml {
+            serde_yaml_ng::from_str(&content)?
+        } else {
+            toml::from_str(&content)?
+        };
+
+        let base = file.parent().unwrap_or(Path::new(""));
+        for segment in &mut storyboard.segments {
+            if let Some(file) = &segment.file {
+                segment.file = Some(base.join(file));
+            }
+        }
+
+        storyboard.validate()?;
+        Ok(storyboard)
+    }
+
+    /// Checks that the storyboard has segments and every segment exactly one source, that all
+    /// durations and speeds are larger than 0 and that no clip starts before 0.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error describing the first invalid value.
+    pub fn validate(&self) -> Result<(), StoryboardError> {
+        if self.segments.is_empty() {
+            return Err(StoryboardError::Empty);
+        }
+        if self
+            .transition_duration
+            .is_some_and(|duration| !is_positive(duration))
+        {
+            return Err(StoryboardError::InvalidTransitionDuration);
+        }
+        for (i, segment) in self.segments.iter().enumerate() {
+            match (&segment.query, &segment.file) {
+                (Some(_), None) | (None, Some(_)) => {}
+                (None, None) => return Err(StoryboardError::NoSource { segment: i + 1 }),
+                (Some(_), Some(_)) => {
+                    return Err(StoryboardError::MultipleSources { segment: i + 1 })
+                }
+            }
+            let positive = [
+                ("duration", segment.duration),
+                ("speed", segment.speed),
+                ("min_duration", segment.min_duration),
+                ("transition_duration", segment.transition_duration),
+            ];
+            if let Some((field, _)) = positive
+                .into_iter()
+                .find(|(_, value)| value.is_some_and(|value| !is_positive(value)))
+            {
+                return Err(StoryboardError::NotPositive {
+                    segment: i + 1,
+                    field,
+                });
+            }
+            if segment
+                .start
+                .is_some_and(|start| !(start >= 0.0 && start.is_finite()))
+            {
+                return Err(StoryboardError::InvalidStart { segment: i + 1 });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the `value` is a finite number larger than 0, which also rules out NaN.
+fn is_positive(value: f64) -> bool {
+    value > 0.0 && value.is_finite()
+}
+
+#[derive(Error, Debug)]
+pub enum StoryboardError {
+    #[error("could not read the storyboard file")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+    #[error("the storyboard is not valid TOML")]
+    Toml {
+        #[from]
+        source: toml::de::Error,
+    },
+    #[error("the storyboard is not valid YAML")]
+    Yaml {
+        #[from]
+        source: serde_yaml_ng::Error,
+    },
+    #[error("the storyboard has no segments")]
+    Empty,
+    #[error("segment {segment} of the storyboard needs a query or a file")]
+    NoSource { segment: usize },
+    #[error("segment {segment} of the storyboard can only have a query or a file, not both")]
+    MultipleSources { segment: usize },
+    #[error("the transition_duration of the storyboard must be larger than 0")]
+    InvalidTransitionDuration,
+    #[error("the {field} of segment {segment} of the storyboard must be larger than 0")]
+    NotPositive { segment: usize, field: &'static str },
+    #[error("the start of segment {segment} of the storyboard can't be before 0")]
+    InvalidStart { segment: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    /// Writes the `content` to a file with the `name` in a temporary directory and loads it.
+    fn load(name: &str, content: &str) -> Result<Storyboard, StoryboardError> {
+        let dir = env::temp_dir().join(format!("time-for-storyboard-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(name);
+        fs::write(&file, content).unwrap();
+        let storyboard = Storyboard::load(&file);
+        fs::remove_file(&file).unwrap();
+        storyboard
+    }
+
+    fn segment(query: &str) -> StoryboardSegment {
+        StoryboardSegment {
+            query: Some(query.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn storyboard(segment: StoryboardSegment) -> Storyboard {
+        Storyboard {
+            segments: vec![segment],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn loads_toml() {
+        let storyboard = load(
+            "loads_toml.toml",
+            r#"
+            transition = "crossfade"
+
+            [[segment]]
+            query = "coffee"
+            duration = 3.0
+
+            [[segment]]
+            file = "clips/back_to_work.webm"
+            transition = "wipe"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(storyboard.transition, Some(TransitionKind::Crossfade));
+        assert_eq!(storyboard.segments.len(), 2);
+        assert_eq!(storyboard.segments[0].query.as_deref(), Some("coffee"));
+        assert_eq!(storyboard.segments[0].duration, Some(3.0));
+        assert_eq!(
+            storyboard.segments[1].transition,
+            Some(TransitionKind::Wipe)
+        );
+    }
+
+    #[test]
+    fn loads_yaml() {
+        let storyboard = load(
+            "loads_yaml.yaml",
+            "transition_duration: 0.25\n\
+             segments:\n\
+             - query: coffee\n\
+             \x20 speed: 1.5\n\
+             - query: tea\n\
+             \x20 boomerang: true\n",
+        )
+        .unwrap();
+        assert_eq!(storyboard.transition_duration, Some(0.25));
+        assert_eq!(storyboard.segments[0].speed, Some(1.5));
+        assert_eq!(storyboard.segments[1].query.as_deref(), Some("tea"));
+        assert_eq!(storyboard.segments[1].boomerang, Some(true));
+    }
+
+    #[test]
+    fn files_are_relative_to_the_storyboard() {
+        let storyboard = load(
+            "files_are_relative.toml",
+            "[[segment]]\nfile = \"clips/coffee.webm\"\n",
+        )
+        .unwrap();
+        let dir = env::temp_dir().join(format!("time-for-storyboard-{}", process::id()));
+        assert_eq!(
+            storyboard.segments[0].file,
+            Some(dir.join("clips/coffee.webm"))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(matches!(
+            load(
+                "unknown_field.toml",
+                "[[segment]]\nquery = \"tea\"\nspeeed = 2.0\n"
+            ),
+            Err(StoryboardError::Toml { .. })
+        ));
+        assert!(matches!(
+            load(
+                "unknown_field.yml",
+                "segments:\n- query: tea\n  speeed: 2.0\n"
+            ),
+            Err(StoryboardError::Yaml { .. })
+        ));
+    }
+
+    #[test]
+    fn load_validates_the_storyboard() {
+        assert!(matches!(
+            load("empty.toml", ""),
+            Err(StoryboardError::Empty)
+        ));
+        assert!(matches!(
+            load(
+                "nan_speed.toml",
+                "[[segment]]\nquery = \"tea\"\nspeed = nan\n"
+            ),
+            Err(StoryboardError::NotPositive {
+                segment: 1,
+                field: "speed"
+            })
+        ));
+    }
+
+    #[test]
+    fn segments_need_exactly_one_source() {
+        assert!(matches!(
+            storyboard(StoryboardSegment::default()).validate(),
+            Err(StoryboardError::NoSource { segment: 1 })
+        ));
+        let both = StoryboardSegment {
+            file: Some(PathBuf::from("coffee.webm")),
+            ..segment("coffee")
+        };
+        assert!(matches!(
+            storyboard(both).validate(),
+            Err(StoryboardError::MultipleSources { segment: 1 })
+        ));
+    }
+
+    #[test]
+    fn durations_and_speeds_must_be_positive() {
+        for value in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let segments = [
+                StoryboardSegment {
+                    duration: Some(value),
+                    ..segment("tea")
+                },
+                StoryboardSegment {
+                    speed: Some(value),
+                    ..segment("tea")
+                },
+                StoryboardSegment {
+                    min_duration: Some(value),
+                    ..segment("tea")
+                },
+                StoryboardSegment {
+                    transition_duration: Some(value),
+                    ..segment("tea")
+                },
+            ];
+            for segment in segments {
+                assert!(
+                    matches!(
+                        storyboard(segment.clone()).validate(),
+                        Err(StoryboardError::NotPositive { segment: 1, .. })
+                    ),
+                    "{segment:?}"
+                );
+            }
+
+            let top_level = Storyboard {
+                transition_duration: Some(value),
+                ..storyboard(segment("tea"))
+            };
+            assert!(matches!(
+                top_level.validate(),
+                Err(StoryboardError::InvalidTransitionDuration)
+            ));
+        }
+    }
+
+    #[test]
+    fn start_must_not_be_negative() {
+        let at = |start| {
+            storyboard(StoryboardSegment {
+                start: Some(start),
+                ..segment("tea")
+            })
+            .validate()
+        };
+        assert!(at(0.0).is_ok());
+        assert!(at(1.5).is_ok());
+        for start in [-0.5, f64::NAN, f64::INFINITY] {
+            assert!(
+                matches!(at(start), Err(StoryboardError::InvalidStart { segment: 1 })),
+                "{start}"
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_valid_values() {
+        let segment = StoryboardSegment {
+            start: Some(0.5),
+            duration: Some(2.0),
+            speed: Some(0.5),
+            min_duration: Some(3.0),
+            transition_duration: Some(0.25),
+            ..segment("tea")
+        };
+        assert!(storyboard(segment).validate().is_ok());
+    }
+}
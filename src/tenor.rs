@@ -4,6 +4,8 @@ use thiserror::Error;
 
 use crate::secrets::TENOR_API_KEY;
 
+const TENOR_URL: &str = "https://tenor.googleapis.com/v2/search";
+
 /// Structure for the parameters used in a Tenor search request
 #[derive(serde::Serialize)]
 struct SearchRequest {
@@ -17,13 +19,87 @@ struct SearchRequest {
     offset: Option<usize>,
 }
 
+impl SearchRequest {
+    fn new(search_term: &str, limit: u8) -> Self {
+        Self {
+            q: search_term.to_string(),
+            key: TENOR_API_KEY.to_string(),
+            limit,
+            offset: None,
+        }
+    }
+}
+
+/// A GIF found on Tenor.
+#[derive(Debug, Clone)]
+pub struct TenorGif {
+    /// The Tenor id of the GIF
+    pub id: String,
+    /// The url of the webm version of the GIF
+    pub url: String,
+}
+
+/// Searches Tenor for the first `limit` GIFs for the given `search_term`.
+///
+/// # Errors
+///
+/// This function will return an error if the request to the TenorApi or
+/// the json parsing of the response was unsuccessful
+pub fn search(search_term: &str, limit: u8) -> Result<Vec<TenorGif>, TenorError> {
+    let client = reqwest::blocking::Client::new();
+    let res = client
+        .request(reqwest::Method::GET, TENOR_URL)
+        .query(&SearchRequest::new(search_term, limit))
+        .send()?;
+
+    // Done: Check for error response like https://stackoverflow.com/a/61429476/10018101
+    // Return correct error if error or normal (just like currently) otherwise
+    read_gifs(res.json()?, limit)
+}
+
+/// Searches Tenor like [`search()`] without blocking the async runtime.
+///
+/// # Errors
+///
+/// This function will return an error if the request to the TenorApi or
+/// the json parsing of the response was unsuccessful
+#[cfg(feature = "async")]
+pub async fn search_async(search_term: &str, limit: u8) -> Result<Vec<TenorGif>, TenorError> {
+    let client = reqwest::Client::new();
+    let res = client
+        .request(reqwest::Method::GET, TENOR_URL)
+        .query(&SearchRequest::new(search_term, limit))
+        .send()
+        .await?;
+    read_gifs(res.json().await?, limit)
+}
+
+/// The first `limit` GIFs of the response `body`.
+fn read_gifs(body: ResponseType, limit: u8) -> Result<Vec<TenorGif>, TenorError> {
+    match body {
+        ResponseType::Err(e) => Err(TenorError::TenorApi {
+            code: e.error.code,
+            message: e.error.message,
+        }),
+        ResponseType::Ok(body) => Ok(body
+            .results
+            .into_iter()
+            .take(limit as usize)
+            .map(|result| TenorGif {
+                id: result.id,
+                url: result.media_formats.webm.url,
+            })
+            .collect()),
+    }
+}
+
 /// Randomly picks one of the first `considered_gifs` for the given `search_term`.
 /// The default value for `considered_gifs` is set to 10.
 ///
 /// # Examples
 ///
-/// ```rust
-/// let random_gif_url = random_gif("Overwatch time", Some(5)).unwrap();
+/// ```rust,ignore
+/// let random_webm_url = random_webm("Overwatch time", Some(5)).unwrap().url;
 /// ```
 /// # Errors
 ///
@@ -33,56 +109,38 @@ pub fn random_webm(
     search_term: &str,
     considered_gifs: Option<u8>,
     // min_duration: Option<u32>,
-) -> Result<String, TenorError> {
-    let tenor_url = "https://tenor.googleapis.com/v2/search";
-    let considered_gifs = considered_gifs.unwrap_or(10);
-    let search_request = SearchRequest {
-        q: search_term.to_string(),
-        key: TENOR_API_KEY.to_string(),
-        limit: considered_gifs,
-        offset: None,
-    };
-
-    let client = reqwest::blocking::Client::new();
-    let res = client
-        .request(reqwest::Method::GET, tenor_url)
-        .query(&search_request)
-        .send()?;
+) -> Result<TenorGif, TenorError> {
+    let gifs = search(search_term, considered_gifs.unwrap_or(10))?;
+    pick_random(search_term, gifs)
+}
 
-    // Done: Check for error response like https://stackoverflow.com/a/61429476/10018101
-    // Return correct error if error or normal (just like currently) otherwise
-    let body: ResponseType = res.json()?;
+/// Randomly picks one of the first `considered_gifs` like [`random_webm()`] without blocking
+/// the async runtime.
+///
+/// # Errors
+///
+/// This function will return an error if the request to the TenorApi,
+/// the json parsing of the response was unsuccessful or there was no gif found
+#[cfg(feature = "async")]
+pub async fn random_webm_async(
+    search_term: &str,
+    considered_gifs: Option<u8>,
+) -> Result<TenorGif, TenorError> {
+    let gifs = search_async(search_term, considered_gifs.unwrap_or(10)).await?;
+    pick_random(search_term, gifs)
+}
 
-    match body {
-        ResponseType::Err(e) => {
-            return Err(TenorError::TenorApi {
-                code: e.error.code,
-                message: e.error.message,
-            });
-        }
-        ResponseType::Ok(body) => {
-            let max_gif = if (body.results.len() as u8) < considered_gifs {
-                body.results.len() as u8
-            } else {
-                considered_gifs
-            };
-
-            if max_gif == 0 {
-                return Err(TenorError::NoGifFound {
-                    query: search_request.q,
-                });
-            }
-
-            let mut rng = thread_rng();
-            let selected_gif = rng.gen_range(0..max_gif) as usize;
-
-            return Ok(body.results[selected_gif as usize]
-                .media_formats
-                .webm
-                .url
-                .clone());
-        }
+fn pick_random(search_term: &str, mut gifs: Vec<TenorGif>) -> Result<TenorGif, TenorError> {
+    if gifs.is_empty() {
+        return Err(TenorError::NoGifFound {
+            query: search_term.to_string(),
+        });
     }
+
+    let mut rng = thread_rng();
+    let selected_gif = rng.gen_range(0..gifs.len());
+
+    Ok(gifs.swap_remove(selected_gif))
 }
 
 #[derive(Error, Debug)]
@@ -121,6 +179,7 @@ struct Body {
 
 #[derive(Deserialize, Debug)]
 struct MediaFormats {
+    id: String,
     media_formats: WebmUrl,
 }
 
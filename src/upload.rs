@@ -0,0 +1,569 @@
+use std::{fmt, path::Path, thread, time::Duration};
+
+use clap::ValueEnum;
+use reqwest::{
+    blocking::{
+        multipart::{Form, Part},
+        Client, Response,
+    },
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{ffmpeg::OutputFormat, imgur, secrets};
+
+/// The user agent 0x0 requires for uploads and deletions.
+const USER_AGENT: &str = concat!("time-for/", env!("CARGO_PKG_VERSION"));
+
+/// The longest delay before a retry a `Retry-After` header can request.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// The services a file can be uploaded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Uploader {
+    /// imgur.com, accepts videos up to 200 MB
+    Imgur,
+    /// catbox.moe, accepts files up to 200 MB
+    Catbox,
+    /// 0x0.st, accepts files up to 512 MB
+    #[value(name = "0x0")]
+    #[serde(rename = "0x0")]
+    ZeroXZero,
+}
+
+impl Uploader {
+    /// The maximum size of a file in the `format` accepted by the service in bytes.
+    pub fn max_size(&self, format: OutputFormat) -> u64 {
+        match self {
+            // Imgur only accepts large files for videos and GIFs
+            Uploader::Imgur if !format.is_video() && format != OutputFormat::Gif => {
+                20 * 1024 * 1024
+            }
+            Uploader::Imgur | Uploader::Catbox => 200 * 1024 * 1024,
+            Uploader::ZeroXZero => 512 * 1024 * 1024,
+        }
+    }
+}
+
+impl fmt::Display for Uploader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Uploader::Imgur => write!(f, "Imgur"),
+            Uploader::Catbox => write!(f, "Catbox"),
+            Uploader::ZeroXZero => write!(f, "0x0"),
+        }
+    }
+}
+
+/// The timeout, retry and account settings used for every upload request.
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    /// The timeout for a single upload request
+    pub timeout: Duration,
+    /// How often a request is retried after a rate limit (429) or server error (5xx)
+    pub retries: u32,
+    /// The delay before the first retry, doubled for every further retry
+    pub backoff: Duration,
+    /// The token of the Imgur account to upload to, uploads are anonymous without it
+    pub imgur_token: Option<imgur::Token>,
+    /// The id of the Imgur album to upload into, requires the `imgur_token`
+    pub imgur_album: Option<String>,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(120),
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            imgur_token: None,
+            imgur_album: None,
+        }
+    }
+}
+
+/// A successfully uploaded file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Upload {
+    /// The public link to the file
+    pub link: String,
+    /// The service the file was uploaded to
+    pub uploader: Uploader,
+    /// The token needed to delete the file again (Imgur's `deletehash` or 0x0's `X-Token`)
+    pub delete_token: Option<String>,
+    /// When the file was uploaded (RFC 3339)
+    pub created: String,
+}
+
+impl Upload {
+    /// The id of the upload, which is the file name in the link without the extension.
+    pub fn id(&self) -> &str {
+        let name = self.link.rsplit('/').next().unwrap_or_default();
+        name.split('.').next().unwrap_or(name)
+    }
+}
+
+/// Uploads the `file` of the `format` to the first of the `uploaders` that accepts it.
+///
+/// Every uploader is retried according to the `options` before falling back to the next one
+/// in the list.
+///
+/// # Errors
+///
+/// This function will return an error if the client could not be created or every uploader
+/// failed, in which case the errors of all uploaders are returned.
+pub fn upload(
+    file: &Path,
+    format: OutputFormat,
+    uploaders: &[Uploader],
+    options: &UploadOptions,
+) -> Result<Upload, UploadError> {
+    let client = Client::builder().timeout(options.timeout).build()?;
+
+    let mut errors = vec![];
+    for &uploader in uploaders {
+        match upload_with_retries(&client, file, format, uploader, options) {
+            Ok((link, delete_token)) => {
+                return Ok(Upload {
+                    link,
+                    uploader,
+                    delete_token,
+                    created: chrono::Local::now().to_rfc3339(),
+                })
+            }
+            Err(e) => errors.push((uploader, e)),
+        }
+    }
+
+    Err(UploadError::AllFailed { errors })
+}
+
+/// Uploads the `file` like [`upload()`] without blocking the async runtime.
+///
+/// # Errors
+///
+/// This function will return an error if the client could not be created, the file could not
+/// be read or every uploader failed, in which case the errors of all uploaders are returned.
+#[cfg(feature = "async")]
+pub async fn upload_async(
+    file: &Path,
+    format: OutputFormat,
+    uploaders: &[Uploader],
+    options: &UploadOptions,
+) -> Result<Upload, UploadError> {
+    let client = reqwest::Client::builder()
+        .timeout(options.timeout)
+        .build()?;
+    let data = tokio::fs::read(file)
+        .await
+        .map_err(|e| UploadError::File { source: e })?;
+
+    let mut errors = vec![];
+    for &uploader in uploaders {
+        match upload_with_retries_async(&client, file, format, &data, uploader, options).await {
+            Ok((link, delete_token)) => {
+                return Ok(Upload {
+                    link,
+                    uploader,
+                    delete_token,
+                    created: chrono::Local::now().to_rfc3339(),
+                })
+            }
+            Err(e) => errors.push((uploader, e)),
+        }
+    }
+
+    Err(UploadError::AllFailed { errors })
+}
+
+/// Uploads the `file` with the `uploader`, retrying on rate limits, server errors and
+/// timeouts with an exponential backoff.
+fn upload_with_retries(
+    client: &Client,
+    file: &Path,
+    format: OutputFormat,
+    uploader: Uploader,
+    options: &UploadOptions,
+) -> Result<(String, Option<String>), UploadError> {
+    let mut attempt = 0;
+    loop {
+        let delay = options.backoff.saturating_mul(2u32.saturating_pow(attempt));
+        let retry_after = match send(client, file, format, uploader, options) {
+            Ok(res) if res.status().is_success() => {
+                let token = delete_token(res.headers());
+                return read_upload(uploader, token, res.text()?);
+            }
+            Ok(res) if is_retryable(res.status()) && attempt < options.retries => {
+                retry_after(res.headers()).unwrap_or(delay)
+            }
+            Ok(res) => {
+                return Err(UploadError::Status {
+                    uploader,
+                    code: res.status().as_u16(),
+                    body: res.text().unwrap_or_default(),
+                })
+            }
+            Err(UploadError::Request { source })
+                if (source.is_timeout() || source.is_connect()) && attempt < options.retries =>
+            {
+                delay
+            }
+            Err(e) => return Err(e),
+        };
+
+        thread::sleep(retry_after);
+        attempt += 1;
+    }
+}
+
+fn send(
+    client: &Client,
+    file: &Path,
+    format: OutputFormat,
+    uploader: Uploader,
+    options: &UploadOptions,
+) -> Result<Response, UploadError> {
+    let upload = UploadRequest::new(uploader, format, options);
+    let part = Part::file(file)
+        .map_err(|e| UploadError::File { source: e })?
+        .mime_str(format.mime_type())?;
+    let mut form = Form::new().part(upload.field, part);
+    for (name, value) in upload.fields {
+        form = form.text(name, value);
+    }
+    let mut request = client.post(upload.url).multipart(form);
+    for (name, value) in upload.headers {
+        request = request.header(name, value);
+    }
+    Ok(request.send()?)
+}
+
+/// Uploads the `data` of the `file` like [`upload_with_retries()`] with the async `client`.
+#[cfg(feature = "async")]
+async fn upload_with_retries_async(
+    client: &reqwest::Client,
+    file: &Path,
+    format: OutputFormat,
+    data: &[u8],
+    uploader: Uploader,
+    options: &UploadOptions,
+) -> Result<(String, Option<String>), UploadError> {
+    let mut attempt = 0;
+    loop {
+        let delay = options.backoff.saturating_mul(2u32.saturating_pow(attempt));
+        let retry_after = match send_async(client, file, format, data, uploader, options).await {
+            Ok(res) if res.status().is_success() => {
+                let token = delete_token(res.headers());
+                return read_upload(uploader, token, res.text().await?);
+            }
+            Ok(res) if is_retryable(res.status()) && attempt < options.retries => {
+                retry_after(res.headers()).unwrap_or(delay)
+            }
+            Ok(res) => {
+                return Err(UploadError::Status {
+                    uploader,
+                    code: res.status().as_u16(),
+                    body: res.text().await.unwrap_or_default(),
+                })
+            }
+            Err(UploadError::Request { source })
+                if (source.is_timeout() || source.is_connect()) && attempt < options.retries =>
+            {
+                delay
+            }
+            Err(e) => return Err(e),
+        };
+
+        tokio::time::sleep(retry_after).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(feature = "async")]
+async fn send_async(
+    client: &reqwest::Client,
+    file: &Path,
+    format: OutputFormat,
+    data: &[u8],
+    uploader: Uploader,
+    options: &UploadOptions,
+) -> Result<reqwest::Response, UploadError> {
+    let upload = UploadRequest::new(uploader, format, options);
+    // The async form can't read files itself, so it gets the data with the file name the
+    // blocking form would use
+    let part = reqwest::multipart::Part::bytes(data.to_vec())
+        .file_name(
+            file.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        )
+        .mime_str(format.mime_type())?;
+    let mut form = reqwest::multipart::Form::new().part(upload.field, part);
+    for (name, value) in upload.fields {
+        form = form.text(name, value);
+    }
+    let mut request = client.post(upload.url).multipart(form);
+    for (name, value) in upload.headers {
+        request = request.header(name, value);
+    }
+    Ok(request.send().await?)
+}
+
+/// The request uploading a file to an uploader, which is the same for the blocking and the
+/// async client.
+struct UploadRequest {
+    url: &'static str,
+    /// The form field of the file
+    field: &'static str,
+    /// The other fields of the form
+    fields: Vec<(&'static str, String)>,
+    headers: Vec<(&'static str, String)>,
+}
+
+impl UploadRequest {
+    /// The request uploading a file of the `format` to the `uploader`.
+    fn new(uploader: Uploader, format: OutputFormat, options: &UploadOptions) -> Self {
+        match uploader {
+            Uploader::Imgur => UploadRequest {
+                url: "https://api.imgur.com/3/upload",
+                field: imgur_field(format),
+                fields: options
+                    .imgur_album
+                    .iter()
+                    .map(|album| ("album", album.clone()))
+                    .collect(),
+                headers: vec![("Authorization", imgur_authorization(options))],
+            },
+            Uploader::Catbox => UploadRequest {
+                url: "https://catbox.moe/user/api.php",
+                field: "fileToUpload",
+                fields: vec![("reqtype", "fileupload".to_string())],
+                headers: vec![],
+            },
+            Uploader::ZeroXZero => UploadRequest {
+                url: "https://0x0.st",
+                field: "file",
+                fields: vec![],
+                headers: vec![("User-Agent", USER_AGENT.to_string())],
+            },
+        }
+    }
+}
+
+/// The form field Imgur expects a file of the `format` in, videos and images use different
+/// ones.
+fn imgur_field(format: OutputFormat) -> &'static str {
+    if format.is_video() {
+        "video"
+    } else {
+        "image"
+    }
+}
+
+/// Reads the link to the uploaded file and the token to delete it (if the service has one)
+/// from the `body` of the successful response with the `X-Token` header `token`.
+fn read_upload(
+    uploader: Uploader,
+    token: Option<String>,
+    body: String,
+) -> Result<(String, Option<String>), UploadError> {
+    match uploader {
+        Uploader::Imgur => {
+            match serde_json::from_str::<ImgurResponse>(&body) {
+                // Remove the dot '.' at the end of the link when uploading webm
+                Ok(resp) => Ok((
+                    resp.data.link.trim_end_matches('.').to_string(),
+                    resp.data.deletehash,
+                )),
+                Err(_) => Err(UploadError::Response { uploader, body }),
+            }
+        }
+        Uploader::Catbox | Uploader::ZeroXZero => {
+            let link = body.trim();
+            if link.starts_with("https://") || link.starts_with("http://") {
+                Ok((link.to_string(), token))
+            } else {
+                Err(UploadError::Response { uploader, body })
+            }
+        }
+    }
+}
+
+/// The token to delete the upload with from the `X-Token` header of 0x0.
+fn delete_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("X-Token")
+        .and_then(|token| token.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Deletes the `upload` from the service it was uploaded to.
+///
+/// # Errors
+///
+/// This function will return an error if there is no token to delete the upload with, the
+/// request fails or the service does not respond with a success status.
+pub fn delete(upload: &Upload, options: &UploadOptions) -> Result<(), UploadError> {
+    let token = upload
+        .delete_token
+        .as_deref()
+        .ok_or_else(|| UploadError::NotDeletable {
+            uploader: upload.uploader,
+            link: upload.link.clone(),
+        })?;
+
+    let client = Client::builder().timeout(options.timeout).build()?;
+    let request = match upload.uploader {
+        Uploader::Imgur => client
+            .delete(format!("https://api.imgur.com/3/image/{}", token))
+            .header("Authorization", imgur_authorization(options)),
+        Uploader::ZeroXZero => client
+            .post(&upload.link)
+            .header("User-Agent", USER_AGENT)
+            .form(&[("token", token), ("delete", "")]),
+        Uploader::Catbox => {
+            return Err(UploadError::NotDeletable {
+                uploader: upload.uploader,
+                link: upload.link.clone(),
+            })
+        }
+    };
+
+    let res = request.send()?;
+    if !res.status().is_success() {
+        return Err(UploadError::Status {
+            uploader: upload.uploader,
+            code: res.status().as_u16(),
+            body: res.text().unwrap_or_default(),
+        });
+    }
+    Ok(())
+}
+
+/// The authorization header value of Imgur requests, with the account token if there is one,
+/// or anonymously with the client id otherwise.
+fn imgur_authorization(options: &UploadOptions) -> String {
+    match &options.imgur_token {
+        Some(token) => imgur::authorization(token),
+        None => format!("Client-ID {}", secrets::IMGUR_CLIENT_ID),
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// The delay requested by the `Retry-After` header in seconds, if there is one, at most
+/// [`MAX_RETRY_AFTER`], so a server can't make the upload wait for hours.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+        .map(|secs| Duration::from_secs(secs).min(MAX_RETRY_AFTER))
+}
+
+#[derive(Error, Debug)]
+pub enum UploadError {
+    #[error("Could not read the file to upload")]
+    File { source: std::io::Error },
+    #[error("There was an error with the upload request:\n{source:?}")]
+    Request {
+        #[from]
+        source: reqwest::Error,
+    },
+    #[error("{uploader} responded with the error {code}: {body:?}")]
+    Status {
+        uploader: Uploader,
+        code: u16,
+        body: String,
+    },
+    #[error("{uploader} responded with an unexpected body: {body:?}")]
+    Response { uploader: Uploader, body: String },
+    #[error("{uploader} uploads can not be deleted without a token: {link}")]
+    NotDeletable { uploader: Uploader, link: String },
+    #[error("All uploaders failed:{}", errors.iter().map(|(uploader, e)| format!("\n- {}: {}", uploader, e)).collect::<String>())]
+    AllFailed {
+        errors: Vec<(Uploader, UploadError)>,
+    },
+}
+
+#[derive(Deserialize)]
+struct ImgurResponse {
+    data: ImgurData,
+}
+
+#[derive(Deserialize, Debug)]
+struct ImgurData {
+    link: String,
+    deletehash: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_reads_seconds() {
+        assert_eq!(retry_after(&headers("5")), Some(Duration::from_secs(5)));
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+    }
+
+    #[test]
+    fn retry_after_is_clamped() {
+        assert_eq!(retry_after(&headers("3600")), Some(MAX_RETRY_AFTER));
+    }
+
+    #[test]
+    fn all_failed_names_every_uploader() {
+        let error = UploadError::AllFailed {
+            errors: vec![
+                (
+                    Uploader::Imgur,
+                    UploadError::File {
+                        source: std::io::Error::other("gone"),
+                    },
+                ),
+                (
+                    Uploader::Catbox,
+                    UploadError::Response {
+                        uploader: Uploader::Catbox,
+                        body: "nope".to_string(),
+                    },
+                ),
+            ],
+        };
+        let message = error.to_string();
+        assert!(message.contains("\n- Imgur: Could not read the file to upload"));
+        assert!(message.contains("\n- Catbox: Catbox responded"));
+    }
+
+    #[test]
+    fn imgur_field_follows_the_format() {
+        assert_eq!(imgur_field(OutputFormat::Webm), "video");
+        assert_eq!(imgur_field(OutputFormat::Mp4), "video");
+        for format in [
+            OutputFormat::Gif,
+            OutputFormat::Webp,
+            OutputFormat::Apng,
+            OutputFormat::Avif,
+        ] {
+            assert_eq!(imgur_field(format), "image", "{format:?}");
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::{fmt, path::Path, time::Duration};
+
+use serde_json::json;
+use thiserror::Error;
+
+/// The timeout for posting to a webhook, including attaching the file.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The kind of service an incoming webhook belongs to, which decides the payload format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookKind {
+    Discord,
+    Slack,
+    Mattermost,
+}
+
+impl WebhookKind {
+    /// Guesses the kind of webhook from its `url`.
+    ///
+    /// Discord and Slack webhooks are recognized by their host, everything else is treated
+    /// as a (self-hosted) Mattermost webhook, which uses the Slack compatible payload.
+    pub fn from_url(url: &str) -> Self {
+        let url = reqwest::Url::parse(url).ok();
+        let host = url
+            .as_ref()
+            .and_then(|url| url.host_str())
+            .unwrap_or_default();
+        let is_domain = |domain: &str| {
+            host == domain
+                || host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.ends_with('.'))
+        };
+
+        if is_domain("discord.com") || is_domain("discordapp.com") {
+            WebhookKind::Discord
+        } else if host == "hooks.slack.com" {
+            WebhookKind::Slack
+        } else {
+            WebhookKind::Mattermost
+        }
+    }
+
+    /// The maximum size of a file attached to the webhook message in bytes, `None` if files
+    /// can't be attached at all.
+    pub fn max_file_size(&self) -> Option<u64> {
+        match self {
+            WebhookKind::Discord => Some(8 * 1024 * 1024),
+            WebhookKind::Slack | WebhookKind::Mattermost => None,
+        }
+    }
+}
+
+impl fmt::Display for WebhookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookKind::Discord => write!(f, "Discord"),
+            WebhookKind::Slack => write!(f, "Slack"),
+            WebhookKind::Mattermost => write!(f, "Mattermost"),
+        }
+    }
+}
+
+/// Fills in the `{query}`, `{text}` and `{link}` placeholders of the message `template`.
+pub fn render_message(template: &str, query: &str, text: &str, link: &str) -> String {
+    template
+        .replace("{query}", query)
+        .replace("{text}", text)
+        .replace("{link}", link)
+}
+
+/// Posts the `message` to the incoming webhook at `url`.
+///
+/// If there is a `link` it is appended to the message (unless the message already contains
+/// it), so the chat client can embed the GIF. Without a link the `file` itself is uploaded,
+/// which only Discord webhooks support.
+///
+/// # Errors
+///
+/// This function will return an error if the file can not be attached to the webhook, the
+/// request fails or the webhook does not respond with a success status.
+pub fn post(url: &str, message: &str, link: Option<&str>, file: &Path) -> Result<(), WebhookError> {
+    let kind = WebhookKind::from_url(url);
+    let client = reqwest::blocking::Client::builder()
+        .timeout(TIMEOUT)
+        .build()?;
+
+    let text = match link {
+        Some(link) if !message.contains(link) => format!("{}\n{}", message, link),
+        _ => message.to_string(),
+    };
+
+    let request = match (kind, link) {
+        (WebhookKind::Discord, Some(_)) => client.post(url).json(&json!({ "content": text })),
+        (WebhookKind::Discord, None) => {
+            let form = reqwest::blocking::multipart::Form::new()
+                .text("payload_json", json!({ "content": text }).to_string())
+                .file("files[0]", file)
+                .map_err(|e| WebhookError::File { source: e })?;
+            client.post(url).multipart(form)
+        }
+        (_, Some(_)) => client.post(url).json(&json!({ "text": text })),
+        (_, None) => {
+            return Err(WebhookError::FileNotSupported {
+                kind,
+                path: file.to_string_lossy().to_string(),
+            })
+        }
+    };
+
+    let res = request.send()?;
+    if !res.status().is_success() {
+        return Err(WebhookError::Status {
+            kind,
+            code: res.status().as_u16(),
+            body: res.text().unwrap_or_default(),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum WebhookError {
+    #[error("{kind} webhooks can only post links, but the file \"{path}\" was not uploaded")]
+    FileNotSupported { kind: WebhookKind, path: String },
+    #[error("Could not attach the file to the webhook message")]
+    File { source: std::io::Error },
+    #[error("There was an error with the webhook request:\n{source:?}")]
+    Request {
+        #[from]
+        source: reqwest::Error,
+    },
+    #[error("The {kind} webhook responded with the error {code}: {body:?}")]
+    Status {
+        kind: WebhookKind,
+        code: u16,
+        body: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_url_recognizes_discord_and_its_subdomains() {
+        for url in [
+            "https://discord.com/api/webhooks/1/abc",
+            "https://ptb.discord.com/api/webhooks/1/abc",
+            "https://discordapp.com/api/webhooks/1/abc",
+            "https://canary.discordapp.com:443/api/webhooks/1/abc",
+        ] {
+            assert_eq!(WebhookKind::from_url(url), WebhookKind::Discord, "{url}");
+        }
+    }
+
+    #[test]
+    fn from_url_rejects_lookalike_hosts() {
+        for url in [
+            "https://evildiscord.com/api/webhooks/1/abc",
+            "https://discord.com.evil.com/api/webhooks/1/abc",
+            "https://discord.com@evil.com/api/webhooks/1/abc",
+            "https://evil.com/discord.com",
+        ] {
+            assert_eq!(WebhookKind::from_url(url), WebhookKind::Mattermost, "{url}");
+        }
+    }
+
+    #[test]
+    fn from_url_recognizes_slack_and_defaults_to_mattermost() {
+        assert_eq!(
+            WebhookKind::from_url("https://hooks.slack.com/services/T/B/X"),
+            WebhookKind::Slack
+        );
+        assert_eq!(
+            WebhookKind::from_url("https://chat.example.com/hooks/abc"),
+            WebhookKind::Mattermost
+        );
+        assert_eq!(WebhookKind::from_url("not a url"), WebhookKind::Mattermost);
+    }
+
+    #[test]
+    fn render_message_replaces_all_placeholders() {
+        assert_eq!(
+            render_message(
+                "{text}: time for {query}! {link}",
+                "tea",
+                "It is 5",
+                "https://x/y"
+            ),
+            "It is 5: time for tea! https://x/y"
+        );
+        assert_eq!(
+            render_message("no placeholders", "a", "b", "c"),
+            "no placeholders"
+        );
+    }
+}